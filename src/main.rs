@@ -8,7 +8,11 @@ extern crate anyhow;
 extern crate clap;
 extern crate do_every;
 extern crate flate2;
+extern crate geo;
+extern crate geojson;
 extern crate read_progress;
+extern crate regex;
+extern crate rstar;
 extern crate rusqlite;
 extern crate serde_json;
 extern crate smallvec;
@@ -16,19 +20,23 @@ extern crate smol_str;
 
 use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Write};
 use std::str::FromStr;
 use std::time::Instant;
 
 use clap::{Arg, ArgAction, Command, value_parser};
-use osmio::{OSMObj, OSMObjBase, OSMObjectType, OSMReader};
+use osmio::obj_types::StringOSMObj;
+use osmio::{Node, OSMObj, OSMObjBase, OSMObjectType, OSMReader, Relation, Way};
+use regex::Regex;
 
 use anyhow::{Context, Result};
 use flate2::Compression;
 use flate2::write::GzEncoder;
+use geo::{BoundingRect, Intersects};
 use read_progress::{ReadWithSize, ReaderWithSize};
+use rstar::{AABB, RTree, RTreeObject};
 use rusqlite::{Connection, OptionalExtension};
 use smallvec::SmallVec;
 use smol_str::SmolStr;
@@ -38,6 +46,47 @@ use tzfile::Tz;
 enum OutputFormat {
     CSV,
     TSV,
+    /// Newline delimited GeoJSON Features
+    GeoJSONSeq,
+}
+
+/// Filename extensions for GeoJSONSeq output
+const GEOJSONSEQ_EXTENSIONS: [&str; 3] = [".geojsonl", ".geojsonseq", ".geojsons"];
+
+/// Key prefixes for the lifecycle of a feature, e.g. `disused:shop=bakery` is a closed bakery
+const LIFECYCLE_PREFIXES: [&str; 10] = [
+    "disused",
+    "abandoned",
+    "was",
+    "demolished",
+    "removed",
+    "razed",
+    "destroyed",
+    "construction",
+    "proposed",
+    "planned",
+];
+
+/// Values too common for `--detect-renames` to pair keys by, e.g. `oneway=yes` → `bridge=yes`
+/// isn't a rename
+const GENERIC_VALUES: [&str; 2] = ["yes", "no"];
+
+/// Splits a lifecycle prefixed key into (prefix, base key), e.g. `disused:shop` → (`disused`,
+/// `shop`)
+fn lifecycle_key(key: &str) -> Option<(&str, &str)> {
+    key.split_once(':')
+        .filter(|(prefix, base)| !base.is_empty() && LIFECYCLE_PREFIXES.contains(prefix))
+}
+
+/// How special characters in strings (e.g. tag values) are written
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Escape {
+    /// `\`, tab, newline & carriage return are written as `\\`, `\t`, `\n` & `\r`
+    Backslash,
+    /// Written as is, with the CSV writer quoting fields as needed
+    Rfc4180,
+    /// Written as is, with no quoting (unless --quote-style)
+    None,
 }
 
 /// Searching for keys
@@ -77,9 +126,371 @@ impl KeyFilter {
     }
 }
 
+/// The key part of a filter expression
+#[derive(Debug, Clone)]
+enum ExpressionKey {
+    Filter(KeyFilter),
+    Regex(Regex),
+}
+
+impl ExpressionKey {
+    fn key_matches(&self, k: &str) -> bool {
+        match self {
+            ExpressionKey::Filter(f) => f.key_matches(k),
+            ExpressionKey::Regex(r) => r.is_match(k),
+        }
+    }
+}
+
+/// The value part of a filter expression
+#[derive(Debug, Clone)]
+enum ExpressionValue {
+    Any,
+    OneOf(SmallVec<[SmolStr; 2]>),
+    NotOneOf(SmallVec<[SmolStr; 2]>),
+    Regex(Regex),
+    NotRegex(Regex),
+}
+
+/// An osmium `tags-filter` style expression, e.g. `w/highway!=footway`, `name~^Tesco`, `!note`
+#[derive(Debug, Clone)]
+struct TagExpression {
+    /// Exclude changes which match this
+    negated: bool,
+    /// (node, way, relation)
+    object_types: (bool, bool, bool),
+    key: ExpressionKey,
+    value: ExpressionValue,
+}
+
+/// Parses from user input.
+///
+/// Format: `[!][TYPES/]KEY[OP VALUE]`. TYPES is some of `nwr`. KEY is a key (with `*`/`rawkey:`
+/// like `-k`), or a regex between `/`s. OP is `=` or `!=` (with a `,` separated list of values),
+/// or `~` or `!~` (with a regex).
+impl FromStr for TagExpression {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let orig = s;
+        let mut s = s.trim();
+        let mut negated = false;
+        if let Some(rest) = s.strip_prefix('!') {
+            negated = true;
+            s = rest;
+        }
+
+        let mut object_types = (true, true, true);
+        if let Some((types, rest)) = s.split_once('/')
+            && !types.is_empty()
+            && types.chars().all(|c| matches!(c, 'n' | 'w' | 'r'))
+        {
+            object_types = (
+                types.contains('n'),
+                types.contains('w'),
+                types.contains('r'),
+            );
+            s = rest;
+            if let Some(rest) = s.strip_prefix('!') {
+                negated = true;
+                s = rest;
+            }
+        }
+
+        let (key, op_value) = if let Some(rest) = s.strip_prefix('/') {
+            let (regex, rest) = rest
+                .split_once('/')
+                .with_context(|| format!("No closing / for key regex in {:?}", orig))?;
+            let regex =
+                Regex::new(regex).with_context(|| format!("Invalid key regex in {:?}", orig))?;
+            (ExpressionKey::Regex(regex), rest)
+        } else {
+            match s.find(['=', '~']) {
+                None => (ExpressionKey::Filter(s.parse().unwrap()), ""),
+                Some(idx) => {
+                    // Include the ! in the operator
+                    let idx = if s[..idx].ends_with('!') {
+                        idx - 1
+                    } else {
+                        idx
+                    };
+                    (ExpressionKey::Filter(s[..idx].parse().unwrap()), &s[idx..])
+                }
+            }
+        };
+
+        let value_list = |values: &str| values.split(',').map(SmolStr::from).collect();
+        let value_regex = |regex: &str| {
+            Regex::new(regex).with_context(|| format!("Invalid value regex in {:?}", orig))
+        };
+        let value = if op_value.is_empty() {
+            ExpressionValue::Any
+        } else if let Some(values) = op_value.strip_prefix("!=") {
+            ExpressionValue::NotOneOf(value_list(values))
+        } else if let Some(regex) = op_value.strip_prefix("!~") {
+            ExpressionValue::NotRegex(value_regex(regex)?)
+        } else if op_value == "=*" {
+            ExpressionValue::Any
+        } else if let Some(values) = op_value.strip_prefix('=') {
+            ExpressionValue::OneOf(value_list(values))
+        } else if let Some(regex) = op_value.strip_prefix('~') {
+            ExpressionValue::Regex(value_regex(regex)?)
+        } else {
+            bail!("Unable to parse filter expression {:?}", orig);
+        };
+
+        Ok(TagExpression {
+            negated,
+            object_types,
+            key,
+            value,
+        })
+    }
+}
+
+impl TagExpression {
+    /// Does this tag of an object match this expression (ignoring `negated`)?
+    fn tag_matches(&self, key: &str, value: &str, object_type: OSMObjectType) -> bool {
+        let change = TagChange {
+            key,
+            last_value: "",
+            last_value_existed: false,
+            curr_value: value,
+            curr_value_exists: true,
+            last_value_author: None,
+            is_cosmetic: false,
+            renamed_from: None,
+        };
+        self.change_matches(&change, object_type)
+    }
+
+    /// Does this change match this expression (ignoring `negated`)?
+    ///
+    /// The value parts match if the old or new value match, and the negative value parts match
+    /// if neither do.
+    fn change_matches(&self, change: &TagChange, object_type: OSMObjectType) -> bool {
+        let type_matches = matches!(
+            (object_type, self.object_types),
+            (OSMObjectType::Node, (true, _, _))
+                | (OSMObjectType::Way, (_, true, _))
+                | (OSMObjectType::Relation, (_, _, true))
+        );
+        if !type_matches
+            || !(self.key.key_matches(change.key)
+                || change.renamed_from.is_some_and(|k| self.key.key_matches(k)))
+        {
+            return false;
+        }
+
+        let mut values = [
+            (change.last_value_existed, change.last_value),
+            (change.curr_value_exists, change.curr_value),
+        ]
+        .into_iter()
+        .filter_map(|(exists, v)| if exists { Some(v) } else { None });
+        match &self.value {
+            ExpressionValue::Any => true,
+            ExpressionValue::OneOf(vals) => values.any(|v| vals.iter().any(|v2| v2 == v)),
+            ExpressionValue::NotOneOf(vals) => !values.any(|v| vals.iter().any(|v2| v2 == v)),
+            ExpressionValue::Regex(r) => values.any(|v| r.is_match(v)),
+            ExpressionValue::NotRegex(r) => !values.any(|v| r.is_match(v)),
+        }
+    }
+}
+
+/// Which objects to include, based on the tags the object has (rather than which tags changed)
+struct ObjectFilter {
+    /// Object must have a tag which matches one of these (if there are any)
+    include_expressions: Vec<TagExpression>,
+    /// Object must have no tags which match these
+    exclude_expressions: Vec<TagExpression>,
+    mode: ObjectFilterMode,
+}
+
+/// Which version(s) of an object are checked with the `ObjectFilter`
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ObjectFilterMode {
+    /// The old or new version of that change
+    EitherVersion,
+    /// Any version of the object
+    Ever,
+    /// The last version of the object
+    Latest,
+}
+
+impl ObjectFilter {
+    /// Does this version of the object match?
+    fn object_matches(&self, obj: &StringOSMObj) -> bool {
+        let object_type = obj.object_type();
+        (self.include_expressions.is_empty()
+            || self
+                .include_expressions
+                .iter()
+                .any(|e| obj.tags().any(|(k, v)| e.tag_matches(k, v, object_type))))
+            && !self
+                .exclude_expressions
+                .iter()
+                .any(|e| obj.tags().any(|(k, v)| e.tag_matches(k, v, object_type)))
+    }
+
+    /// Does this object's history (all the versions of one object) match?
+    fn history_matches(&self, history: &[StringOSMObj]) -> bool {
+        match self.mode {
+            ObjectFilterMode::EitherVersion | ObjectFilterMode::Ever => {
+                history.iter().any(|o| self.object_matches(o))
+            }
+            ObjectFilterMode::Latest => history.last().is_some_and(|o| self.object_matches(o)),
+        }
+    }
+}
+
+/// Which changesets to include, based on the changeset's tags
+struct ChangesetFilter {
+    lookup: ChangesetTagLookup,
+    /// Changeset must have a tag which matches one of these (if there are any)
+    include_expressions: Vec<TagExpression>,
+    /// Changeset must have no tags which match these
+    exclude_expressions: Vec<TagExpression>,
+    /// The last changeset id looked up, and whether it matched
+    last_result: Option<(u32, bool)>,
+}
+
+impl ChangesetFilter {
+    /// Does this changeset match? Type prefixes in the expressions match the type of the object
+    /// which was changed.
+    fn changeset_matches(&mut self, changeset_id: u32, object_type: OSMObjectType) -> Result<bool> {
+        if let Some((last_id, last_result)) = self.last_result
+            && last_id == changeset_id
+            && self.expressions_have_no_types()
+        {
+            return Ok(last_result);
+        }
+
+        let tags = self.lookup.tags(changeset_id)?.unwrap_or_default();
+        let result = (self.include_expressions.is_empty()
+            || self
+                .include_expressions
+                .iter()
+                .any(|e| tags.iter().any(|(k, v)| e.tag_matches(k, v, object_type))))
+            && !self
+                .exclude_expressions
+                .iter()
+                .any(|e| tags.iter().any(|(k, v)| e.tag_matches(k, v, object_type)));
+        self.last_result = Some((changeset_id, result));
+        Ok(result)
+    }
+
+    /// True iff the result doesn't depend on the object type
+    fn expressions_have_no_types(&self) -> bool {
+        self.include_expressions
+            .iter()
+            .chain(self.exclude_expressions.iter())
+            .all(|e| e.object_types == (true, true, true))
+    }
+}
+
+/// Read keys (in the same format as `-k`) from a file, one per line. Empty lines & lines starting
+/// with `#` are ignored.
+fn read_keys_file(filename: &str) -> Result<Vec<KeyFilter>> {
+    let contents = std::fs::read_to_string(filename)
+        .with_context(|| format!("reading keys file {}", filename))?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| KeyFilter::from_str(line).unwrap())
+        .collect())
+}
+
+/// Parse a `KEY=VALUE` argument. The value can contain `=`
+fn parse_kv(kv: &str) -> Result<(SmolStr, SmolStr)> {
+    let (k, v) = kv
+        .split_once('=')
+        .with_context(|| format!("{:?} is not in KEY=VALUE format", kv))?;
+    Ok((k.into(), v.into()))
+}
+
+/// Read ids (user ids, changeset ids, object ids…) from a file, one per line. Empty lines & lines
+/// starting with `#` are ignored, as is anything after the first space (like `osmium getid`).
+fn read_ids_file<T>(filename: &str) -> Result<Vec<T>>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    let contents = std::fs::read_to_string(filename)
+        .with_context(|| format!("reading id file {}", filename))?;
+    contents
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|id| !id.starts_with('#'))
+        .map(|id| {
+            id.parse()
+                .map_err(Into::into)
+                .with_context(|| format!("Invalid id {:?} in {}", id, filename))
+        })
+        .collect()
+}
+
+/// An OSM object type & id, in the same format as the `id` column, e.g. `n123` or `w456`. A bare
+/// number is a node id, like `osmium getid`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct ObjectId(OSMObjectType, i64);
+
+// OSMObjectType doesn't implement Hash
+impl std::hash::Hash for ObjectId {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.0 as u8).hash(state);
+        self.1.hash(state);
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (object_type, id) = match s
+            .chars()
+            .next()
+            .and_then(|c| OSMObjectType::try_from(c).ok())
+        {
+            Some(object_type) => (object_type, &s[1..]),
+            None => (OSMObjectType::Node, s),
+        };
+        let id = id
+            .parse()
+            .with_context(|| format!("Invalid object id {:?}", s))?;
+        Ok(ObjectId(object_type, id))
+    }
+}
+
+/// Read filter expressions from a file, one per line. Empty lines & lines starting with `#` are
+/// ignored, so values can contain `#` (e.g. `colour=#ff0000`, `name~Store #5`).
+fn read_expressions_file(filename: &str) -> Result<Vec<TagExpression>> {
+    let contents = std::fs::read_to_string(filename)
+        .with_context(|| format!("reading expressions file {}", filename))?;
+    contents
+        .lines()
+        .map(strip_comment)
+        .filter(|line| !line.is_empty())
+        .map(TagExpression::from_str)
+        .collect()
+}
+
+/// Trim this line of a filter file, returning `""` for a `#` comment line. A `#` anywhere else is
+/// part of the expression.
+fn strip_comment(line: &str) -> &str {
+    let line = line.trim();
+    if line.starts_with('#') { "" } else { line }
+}
+
 #[derive(Debug, PartialEq)]
 enum Column {
     Key,
+    /// The key of the old/new value, differs from `Key` for renames (`--detect-renames`)
+    OldKey,
+    NewKey,
+    /// Lifecycle prefix of the old/new key (e.g. `disused`)
+    LifecycleFrom,
+    LifecycleTo,
     NewValue,
     OldValue,
     Value,
@@ -98,8 +509,67 @@ enum Column {
 
     ChangesetTag(String),
 
+    /// Value of another tag on the new version
+    Tag(String),
+    /// Value of another tag on the old version
+    OldTag(String),
+    AllTagsJson,
+    OldAllTagsJson,
+    CosmeticChange,
+
+    /// Location of the new version of a node
+    Lat,
+    Lon,
+    /// Location of the old version of a node
+    OldLat,
+    OldLon,
+    /// WKT POINT of the new version of a node
+    Point,
+    /// Average location of the nodes of the new version
+    CentroidLat,
+    CentroidLon,
+    /// Bounding box of the new version
+    Bbox,
+    /// Which region(s) (from --regions) the new version is in
+    Region,
+
     TagCountDelta,
     ValueCountDelta,
+    MemberCountDelta,
+
+    /// Keys added/removed/modified in this version (with `LineType::Version`)
+    AddedKeys,
+    RemovedKeys,
+    ModifiedKeys,
+    NumAddedKeys,
+    NumRemovedKeys,
+    NumModifiedKeys,
+    NumTagsBefore,
+    NumTagsAfter,
+
+    /// First & last timestamp of a changeset (with `LineType::Changeset`)
+    FirstIsoDatetime,
+    LastIsoDatetime,
+    /// Number of objects changed in a changeset (with `LineType::Changeset`)
+    NumNodes,
+    NumWays,
+    NumRelations,
+    /// Time period (from `--time-bucket`) & number of changes (with `LineType::Transition`)
+    Period,
+    Count,
+    ChangeKind,
+
+    OldUsername,
+    OldUid,
+    OldIsoDatetime,
+    OldChangesetId,
+    SecondsSincePreviousVersion,
+
+    ValueAuthorVersion,
+    ValueAuthorUsername,
+    ValueAuthorUid,
+    ValueAuthorIsoDatetime,
+    ValueAuthorChangesetId,
 }
 
 impl FromStr for Column {
@@ -110,8 +580,19 @@ impl FromStr for Column {
             let res = parse_tz_fmt(tz_fmt)?;
             return Ok(Column::DatetimeFmt(res.0, res.1, res.2));
         }
+        // OSM keys are case sensitive
+        if let Some(key) = val.trim().strip_prefix("tag.") {
+            return Ok(Column::Tag(key.to_string()));
+        }
+        if let Some(key) = val.trim().strip_prefix("old_tag.") {
+            return Ok(Column::OldTag(key.to_string()));
+        }
         match val.to_lowercase().trim() {
             "key" => Ok(Column::Key),
+            "old_key" => Ok(Column::OldKey),
+            "new_key" => Ok(Column::NewKey),
+            "lifecycle_from" => Ok(Column::LifecycleFrom),
+            "lifecycle_to" => Ok(Column::LifecycleTo),
             "new_value" => Ok(Column::NewValue),
             "old_value" => Ok(Column::OldValue),
             "value" => Ok(Column::Value),
@@ -129,6 +610,47 @@ impl FromStr for Column {
             )),
             "tag_count_delta" => Ok(Column::TagCountDelta),
             "value_count_delta" => Ok(Column::ValueCountDelta),
+            "member_count_delta" => Ok(Column::MemberCountDelta),
+            "added_keys" => Ok(Column::AddedKeys),
+            "removed_keys" => Ok(Column::RemovedKeys),
+            "modified_keys" => Ok(Column::ModifiedKeys),
+            "n_added_keys" => Ok(Column::NumAddedKeys),
+            "n_removed_keys" => Ok(Column::NumRemovedKeys),
+            "n_modified_keys" => Ok(Column::NumModifiedKeys),
+            "n_tags_before" => Ok(Column::NumTagsBefore),
+            "n_tags_after" => Ok(Column::NumTagsAfter),
+            "first_datetime" | "first_iso_datetime" => Ok(Column::FirstIsoDatetime),
+            "last_datetime" | "last_iso_datetime" => Ok(Column::LastIsoDatetime),
+            "n_nodes" => Ok(Column::NumNodes),
+            "n_ways" => Ok(Column::NumWays),
+            "n_relations" => Ok(Column::NumRelations),
+            "period" => Ok(Column::Period),
+            "count" => Ok(Column::Count),
+            "change_kind" => Ok(Column::ChangeKind),
+            "cosmetic_change" => Ok(Column::CosmeticChange),
+            "all_tags_json" => Ok(Column::AllTagsJson),
+            "old_all_tags_json" => Ok(Column::OldAllTagsJson),
+            "lat" => Ok(Column::Lat),
+            "lon" => Ok(Column::Lon),
+            "old_lat" => Ok(Column::OldLat),
+            "old_lon" => Ok(Column::OldLon),
+            "point" => Ok(Column::Point),
+            "centroid_lat" => Ok(Column::CentroidLat),
+            "centroid_lon" => Ok(Column::CentroidLon),
+            "bbox" => Ok(Column::Bbox),
+            "region" => Ok(Column::Region),
+            "old_username" => Ok(Column::OldUsername),
+            "old_uid" => Ok(Column::OldUid),
+            "old_datetime" | "old_iso_datetime" | "old_iso_timestamp" => Ok(Column::OldIsoDatetime),
+            "old_changeset_id" => Ok(Column::OldChangesetId),
+            "seconds_since_previous_version" => Ok(Column::SecondsSincePreviousVersion),
+            "value_author_version" => Ok(Column::ValueAuthorVersion),
+            "value_author_username" => Ok(Column::ValueAuthorUsername),
+            "value_author_uid" => Ok(Column::ValueAuthorUid),
+            "value_author_datetime" | "value_author_iso_datetime" => {
+                Ok(Column::ValueAuthorIsoDatetime)
+            }
+            "value_author_changeset_id" => Ok(Column::ValueAuthorChangesetId),
             "object_type_short" | "osm_type_short" => Ok(Column::ObjectTypeShort),
             "object_type_long" | "osm_type_long" => Ok(Column::ObjectTypeLong),
 
@@ -148,13 +670,121 @@ fn parse_tz_fmt(val: &str) -> Result<(String, Tz, String)> {
 }
 
 impl Column {
+    /// Does this column need the location of the object?
+    fn is_geometry(&self) -> bool {
+        matches!(
+            self,
+            Column::Lat
+                | Column::Lon
+                | Column::OldLat
+                | Column::OldLon
+                | Column::Point
+                | Column::CentroidLat
+                | Column::CentroidLon
+                | Column::Bbox
+                | Column::Region
+        )
+    }
+
+    /// Does this column need the location of way & relation nodes?
+    fn needs_location_store(&self) -> bool {
+        matches!(
+            self,
+            Column::CentroidLat | Column::CentroidLon | Column::Bbox | Column::Region
+        )
+    }
+
+    /// Is this column always a number (or empty)? Used for the type of GeoJSON properties
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Column::RawId
+                | Column::NewVersion
+                | Column::OldVersion
+                | Column::EpochDatetime
+                | Column::Uid
+                | Column::ChangesetId
+                | Column::Lat
+                | Column::Lon
+                | Column::OldLat
+                | Column::OldLon
+                | Column::CentroidLat
+                | Column::CentroidLon
+                | Column::TagCountDelta
+                | Column::ValueCountDelta
+                | Column::MemberCountDelta
+                | Column::NumAddedKeys
+                | Column::NumRemovedKeys
+                | Column::NumModifiedKeys
+                | Column::NumTagsBefore
+                | Column::NumTagsAfter
+                | Column::NumNodes
+                | Column::NumWays
+                | Column::NumRelations
+                | Column::Count
+                | Column::OldUid
+                | Column::OldChangesetId
+                | Column::SecondsSincePreviousVersion
+                | Column::ValueAuthorVersion
+                | Column::ValueAuthorUid
+                | Column::ValueAuthorChangesetId
+        )
+    }
+
     fn is_changeset_tag(&self) -> bool {
         matches!(self, Column::ChangesetTag(_))
     }
 
+    /// Can this column be used with `LineType::Changeset`?
+    fn is_changeset_summary(&self) -> bool {
+        matches!(
+            self,
+            Column::ChangesetId
+                | Column::Uid
+                | Column::Username
+                | Column::IsoDatetime
+                | Column::FirstIsoDatetime
+                | Column::LastIsoDatetime
+                | Column::NumNodes
+                | Column::NumWays
+                | Column::NumRelations
+                | Column::AddedKeys
+                | Column::RemovedKeys
+                | Column::ModifiedKeys
+                | Column::NumAddedKeys
+                | Column::NumRemovedKeys
+                | Column::NumModifiedKeys
+                | Column::TagCountDelta
+                | Column::ChangesetTag(_)
+        )
+    }
+
+    /// Can this column be used with `LineType::Transition`?
+    fn is_transition_summary(&self) -> bool {
+        matches!(
+            self,
+            Column::Key | Column::OldValue | Column::NewValue | Column::Period | Column::Count
+        )
+    }
+
+    fn is_value_author(&self) -> bool {
+        matches!(
+            self,
+            Column::ValueAuthorVersion
+                | Column::ValueAuthorUsername
+                | Column::ValueAuthorUid
+                | Column::ValueAuthorIsoDatetime
+                | Column::ValueAuthorChangesetId
+        )
+    }
+
     fn header(&self) -> Cow<'_, str> {
         match self {
             Column::Key => "key".into(),
+            Column::OldKey => "old_key".into(),
+            Column::NewKey => "new_key".into(),
+            Column::LifecycleFrom => "lifecycle_from".into(),
+            Column::LifecycleTo => "lifecycle_to".into(),
             Column::NewValue => "new_value".into(),
             Column::OldValue => "old_value".into(),
             Column::Value => "value".into(),
@@ -171,8 +801,49 @@ impl Column {
             Column::Uid => "uid".into(),
             Column::ChangesetId => "changeset_id".into(),
             Column::ChangesetTag(t) => format!("changeset_{}", t).into(),
+            Column::Tag(k) => format!("tag_{}", k).into(),
+            Column::OldTag(k) => format!("old_tag_{}", k).into(),
+            Column::CosmeticChange => "cosmetic_change".into(),
+            Column::Lat => "lat".into(),
+            Column::Lon => "lon".into(),
+            Column::OldLat => "old_lat".into(),
+            Column::OldLon => "old_lon".into(),
+            Column::Point => "point".into(),
+            Column::CentroidLat => "centroid_lat".into(),
+            Column::CentroidLon => "centroid_lon".into(),
+            Column::Bbox => "bbox".into(),
+            Column::Region => "region".into(),
+            Column::AllTagsJson => "all_tags_json".into(),
+            Column::OldAllTagsJson => "old_all_tags_json".into(),
             Column::TagCountDelta => "tag_count_delta".into(),
             Column::ValueCountDelta => "value_count_delta".into(),
+            Column::MemberCountDelta => "member_count_delta".into(),
+            Column::AddedKeys => "added_keys".into(),
+            Column::RemovedKeys => "removed_keys".into(),
+            Column::ModifiedKeys => "modified_keys".into(),
+            Column::NumAddedKeys => "n_added_keys".into(),
+            Column::NumRemovedKeys => "n_removed_keys".into(),
+            Column::NumModifiedKeys => "n_modified_keys".into(),
+            Column::NumTagsBefore => "n_tags_before".into(),
+            Column::NumTagsAfter => "n_tags_after".into(),
+            Column::FirstIsoDatetime => "first_datetime".into(),
+            Column::LastIsoDatetime => "last_datetime".into(),
+            Column::NumNodes => "n_nodes".into(),
+            Column::NumWays => "n_ways".into(),
+            Column::NumRelations => "n_relations".into(),
+            Column::Period => "period".into(),
+            Column::Count => "count".into(),
+            Column::ChangeKind => "change_kind".into(),
+            Column::OldUsername => "old_username".into(),
+            Column::OldUid => "old_uid".into(),
+            Column::OldIsoDatetime => "old_iso_datetime".into(),
+            Column::OldChangesetId => "old_changeset_id".into(),
+            Column::SecondsSincePreviousVersion => "seconds_since_previous_version".into(),
+            Column::ValueAuthorVersion => "value_author_version".into(),
+            Column::ValueAuthorUsername => "value_author_username".into(),
+            Column::ValueAuthorUid => "value_author_uid".into(),
+            Column::ValueAuthorIsoDatetime => "value_author_iso_datetime".into(),
+            Column::ValueAuthorChangesetId => "value_author_changeset_id".into(),
             Column::ObjectTypeShort => "object_type_short".into(),
             Column::ObjectTypeLong => "object_type_long".into(),
         }
@@ -182,6 +853,360 @@ impl Column {
 enum LineType {
     OldNewValue,
     SeparateLines,
+    /// One line per object version, summarising the changes
+    Version,
+    /// One line per changeset, summarising the changes
+    Changeset,
+    /// One line per old value → new value, with how often it happened
+    Transition,
+    /// One line per tag of the last version of each object
+    Latest,
+}
+
+/// How the changes are grouped by time, for `LineType::Transition`
+#[derive(Debug, Clone, Copy)]
+enum TimeBucket {
+    Year,
+    Month,
+    Day,
+}
+
+impl TimeBucket {
+    /// The period this time is in, e.g. `2020-03` for `Month`
+    fn period<'a>(&self, iso_datetime: &'a str) -> &'a str {
+        &iso_datetime[..match self {
+            TimeBucket::Year => 4,
+            TimeBucket::Month => 7,
+            TimeBucket::Day => 10,
+        }]
+    }
+}
+
+/// The changes in one changeset, for `LineType::Changeset`
+#[derive(Debug)]
+struct ChangesetSummary {
+    uid: Option<u32>,
+    user: Option<SmolStr>,
+    first_timestamp: Option<osmio::TimestampFormat>,
+    last_timestamp: Option<osmio::TimestampFormat>,
+    /// Number of nodes, ways & relations with changes
+    num_objects: [u64; 3],
+    /// The last object counted, since all the changes of an object version are together
+    last_object: Option<ObjectId>,
+    /// Number of changes per key
+    added_keys: HashMap<SmolStr, u64>,
+    removed_keys: HashMap<SmolStr, u64>,
+    modified_keys: HashMap<SmolStr, u64>,
+    tag_count_delta: i64,
+}
+
+impl ChangesetSummary {
+    fn new(obj: &StringOSMObj) -> Self {
+        ChangesetSummary {
+            uid: obj.uid(),
+            user: obj.user().map(SmolStr::from),
+            first_timestamp: obj.timestamp().clone(),
+            last_timestamp: obj.timestamp().clone(),
+            num_objects: [0; 3],
+            last_object: None,
+            added_keys: HashMap::new(),
+            removed_keys: HashMap::new(),
+            modified_keys: HashMap::new(),
+            tag_count_delta: 0,
+        }
+    }
+
+    fn add(&mut self, obj: &StringOSMObj, change: &TagChange) {
+        let epoch = |t: &Option<osmio::TimestampFormat>| t.as_ref().map(|t| t.to_epoch_number());
+        if epoch(obj.timestamp()) < epoch(&self.first_timestamp) {
+            self.first_timestamp = obj.timestamp().clone();
+        }
+        if epoch(obj.timestamp()) > epoch(&self.last_timestamp) {
+            self.last_timestamp = obj.timestamp().clone();
+        }
+
+        let oid = ObjectId(obj.object_type(), obj.id());
+        if self.last_object != Some(oid) {
+            self.last_object = Some(oid);
+            self.num_objects[match obj.object_type() {
+                OSMObjectType::Node => 0,
+                OSMObjectType::Way => 1,
+                OSMObjectType::Relation => 2,
+            }] += 1;
+        }
+
+        if let Some(old_key) = change.renamed_from {
+            *self.removed_keys.entry(old_key.into()).or_default() += 1;
+            *self.added_keys.entry(change.key.into()).or_default() += 1;
+            return;
+        }
+        let (keys, delta) = match (change.last_value_existed, change.curr_value_exists) {
+            (false, true) => (&mut self.added_keys, 1),
+            (true, false) => (&mut self.removed_keys, -1),
+            (true, true) => (&mut self.modified_keys, 0),
+            (false, false) => unreachable!(),
+        };
+        *keys.entry(change.key.into()).or_default() += 1;
+        self.tag_count_delta += delta;
+    }
+}
+
+/// The most common keys, as `KEY=COUNT;KEY=COUNT…`, most common first. `top_n` of 0 is all
+fn top_keys(keys: &HashMap<SmolStr, u64>, top_n: usize) -> String {
+    let top_n = if top_n == 0 { usize::MAX } else { top_n };
+    let mut keys: Vec<(&SmolStr, &u64)> = keys.iter().collect();
+    keys.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    keys.iter()
+        .take(top_n)
+        .map(|(k, n)| format!("{}={}", k, n))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// The changes of one object version, for `LineType::Version`
+#[derive(Debug, Default)]
+struct VersionSummary {
+    added_keys: Vec<String>,
+    removed_keys: Vec<String>,
+    modified_keys: Vec<String>,
+}
+
+impl VersionSummary {
+    fn add(&mut self, change: &TagChange) {
+        if let Some(old_key) = change.renamed_from {
+            self.removed_keys.push(old_key.to_string());
+            self.added_keys.push(change.key.to_string());
+            return;
+        }
+        let keys = match (change.last_value_existed, change.curr_value_exists) {
+            (false, true) => &mut self.added_keys,
+            (true, false) => &mut self.removed_keys,
+            (true, true) => &mut self.modified_keys,
+            (false, false) => unreachable!(),
+        };
+        // One change per key, except for way nodes/relation members, or with --split-values
+        if keys.last().is_none_or(|k| k != change.key) {
+            keys.push(change.key.to_string());
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added_keys.is_empty() && self.removed_keys.is_empty() && self.modified_keys.is_empty()
+    }
+}
+
+/// One changed tag (or way node/relation member) of an object
+#[derive(Debug, Clone, Copy)]
+struct TagChange<'a> {
+    key: &'a str,
+    last_value: &'a str,
+    last_value_existed: bool,
+    curr_value: &'a str,
+    curr_value_exists: bool,
+    /// Which version set the old value (if tracked)
+    last_value_author: Option<&'a ValueAuthor>,
+    /// Are the old & new values the same after normalisation?
+    is_cosmetic: bool,
+    /// With `--detect-renames`, the key this value was moved from
+    renamed_from: Option<&'a str>,
+}
+
+impl TagChange<'_> {
+    /// The key of the old value
+    fn old_key(&self) -> &str {
+        self.renamed_from.unwrap_or(self.key)
+    }
+}
+
+/// How tag values are normalised before comparing them
+#[derive(Debug, Default, Clone, Copy)]
+struct Normalisation {
+    /// Remove leading & trailing whitespace
+    trim: bool,
+    /// Compare lowercase values
+    case_fold: bool,
+    /// Treat `;` separated values as a sorted, deduplicated list
+    semicolon: bool,
+}
+
+/// Parses from user input, a comma separated list of modes
+impl FromStr for Normalisation {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut normalisation = Normalisation::default();
+        for mode in s.split(',') {
+            match mode.trim() {
+                "trim" => normalisation.trim = true,
+                "case" => normalisation.case_fold = true,
+                "semicolon" => normalisation.semicolon = true,
+                other => bail!(
+                    "Unknown normalisation {:?}, must be trim, case or semicolon",
+                    other
+                ),
+            }
+        }
+        Ok(normalisation)
+    }
+}
+
+impl Normalisation {
+    fn is_enabled(&self) -> bool {
+        self.trim || self.case_fold || self.semicolon
+    }
+
+    fn normalise<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let mut value: Cow<'a, str> = value.into();
+        if self.trim && value.trim().len() != value.len() {
+            value = value.trim().to_string().into();
+        }
+        // Before sorting the list, so items which only differ in case are the same
+        if self.case_fold && value.chars().any(char::is_uppercase) {
+            value = value.to_lowercase().into();
+        }
+        if self.semicolon && value.contains(';') {
+            let mut parts: Vec<&str> = value
+                .split(';')
+                .map(|v| if self.trim { v.trim() } else { v })
+                .collect();
+            parts.sort_unstable();
+            parts.dedup();
+            value = parts.join(";").into();
+        }
+        value
+    }
+}
+
+/// The version of an object which set a tag to its current value
+#[derive(Debug, Clone)]
+struct ValueAuthor {
+    version: u32,
+    user: SmolStr,
+    uid: u32,
+    timestamp: Option<osmio::TimestampFormat>,
+    changeset_id: u32,
+}
+
+impl ValueAuthor {
+    fn from_obj(obj: &StringOSMObj) -> Self {
+        ValueAuthor {
+            version: obj.version().unwrap(),
+            user: obj.user().unwrap_or("").into(),
+            uid: obj.uid().unwrap_or(0),
+            timestamp: obj.timestamp().clone(),
+            changeset_id: obj.changeset_id().unwrap_or(0),
+        }
+    }
+}
+
+/// Update, for each tag of the object, which version set the current value
+fn update_value_authors(
+    value_authors: &mut HashMap<SmolStr, ValueAuthor>,
+    prev: Option<&StringOSMObj>,
+    curr: &StringOSMObj,
+) {
+    if prev.is_none() {
+        value_authors.clear();
+    }
+    value_authors.retain(|k, _| curr.has_tag(k));
+    for (k, v) in curr.tags() {
+        if prev.and_then(|p| p.tag(k)) != Some(v) || !value_authors.contains_key(k) {
+            value_authors.insert(k.into(), ValueAuthor::from_obj(curr));
+        }
+    }
+}
+
+/// Which tag changes to include in the output
+struct TagFilter {
+    only_include_keys: SmallVec<[KeyFilter; 2]>,
+    only_include_tags: SmallVec<[(SmolStr, SmolStr); 2]>,
+    exclude_keys: SmallVec<[KeyFilter; 2]>,
+    exclude_tags: SmallVec<[(SmolStr, SmolStr); 2]>,
+    /// Which kinds of tag changes to include: (added, removed, modified)
+    only_include_change_kinds: (bool, bool, bool),
+    /// The old value must be one of these (if there are any). `*` matches any value
+    only_include_from: SmallVec<[(SmolStr, SmolStr); 1]>,
+    /// The new value must be one of these (if there are any). `*` matches any value
+    only_include_to: SmallVec<[(SmolStr, SmolStr); 1]>,
+    /// Changes must match one of these (if there are any)
+    include_expressions: Vec<TagExpression>,
+    /// Changes must match none of these
+    exclude_expressions: Vec<TagExpression>,
+    /// Applied to the values before comparing with KEY=VALUE filters
+    normalisation: Normalisation,
+    /// Keys (& tags) also match lifecycle prefixed keys, e.g. `shop` matches `disused:shop`
+    lifecycle: bool,
+}
+
+impl TagFilter {
+    /// Should changes to this key be included? Checked before looking at the values
+    fn key_passes(&self, key: &str) -> bool {
+        let base_key = lifecycle_key(key)
+            .filter(|_| self.lifecycle)
+            .map(|(_, base)| base);
+        let key_matches =
+            |k: &KeyFilter| k.key_matches(key) || base_key.is_some_and(|b| k.key_matches(b));
+        (self.only_include_keys.is_empty() || self.only_include_keys.iter().any(key_matches))
+            && !self.exclude_keys.iter().any(key_matches)
+            && (self.include_expressions.is_empty()
+                || self
+                    .include_expressions
+                    .iter()
+                    .any(|e| e.key.key_matches(key)))
+            && !self.exclude_expressions.iter().any(|e| {
+                matches!(e.value, ExpressionValue::Any)
+                    && e.object_types == (true, true, true)
+                    && e.key.key_matches(key)
+            })
+    }
+
+    fn change_passes(&self, change: &TagChange, object_type: OSMObjectType) -> bool {
+        let passes_change_kind = matches!(
+            (
+                change.last_value_existed,
+                change.curr_value_exists,
+                self.only_include_change_kinds
+            ),
+            (false, true, (true, _, _)) | (true, false, (_, true, _)) | (true, true, (_, _, true))
+        );
+        // The KEY=VALUE filters have already been normalised
+        let last_value: &str = &self.normalisation.normalise(change.last_value);
+        let curr_value: &str = &self.normalisation.normalise(change.curr_value);
+        let value_matches = |(k, v): &(SmolStr, SmolStr), key: &str, exists: bool, value: &str| {
+            k == key && exists && (v == "*" || v == value)
+        };
+        let same_key = |k: &SmolStr, key: &str| {
+            k == key || (self.lifecycle && lifecycle_key(key).is_some_and(|(_, base)| k == base))
+        };
+        let tag_matches = |k: &SmolStr, v: &SmolStr| {
+            (same_key(k, change.key) || same_key(k, change.old_key()))
+                && (v == last_value || v == curr_value)
+        };
+        passes_change_kind
+            && (self.only_include_from.is_empty()
+                || self.only_include_from.iter().any(|kv| {
+                    value_matches(kv, change.old_key(), change.last_value_existed, last_value)
+                }))
+            && (self.only_include_to.is_empty()
+                || self
+                    .only_include_to
+                    .iter()
+                    .any(|kv| value_matches(kv, change.key, change.curr_value_exists, curr_value)))
+            && (self.only_include_tags.is_empty()
+                || self
+                    .only_include_tags
+                    .iter()
+                    .any(|(k, v)| tag_matches(k, v)))
+            && !self.exclude_tags.iter().any(|(k, v)| tag_matches(k, v))
+            && (self.include_expressions.is_empty()
+                || self
+                    .include_expressions
+                    .iter()
+                    .any(|e| e.change_matches(change, object_type)))
+            && !self
+                .exclude_expressions
+                .iter()
+                .any(|e| e.change_matches(change, object_type))
+    }
 }
 
 fn main() -> Result<()> {
@@ -261,7 +1286,137 @@ fn main() -> Result<()> {
              .multiple(true).number_of_values(1)
              .help("Only include changes with this KEY & VALUE (can be specified multiple times)")
              )
+        .arg(Arg::new("normalise")
+             .long("normalise")
+             .value_name("trim,case,semicolon")
+             .takes_value(true).required(false)
+             .help("Normalise tag values (comma separated modes) before comparing them")
+             .long_help("Normalise tag values before comparing them. Changes which are the same after normalisation are not included (unless --include-cosmetic-changes). KEY=VALUE filters (e.g. -t) also compare normalised values. Comma separated list of:\ntrim = remove leading & trailing whitespace\ncase = ignore upper/lower case\nsemicolon = treat ; separated values as a sorted list, with no duplicates (e.g. `b;a;a` is the same as `a;b`)")
+             )
+        .arg(Arg::new("include-cosmetic-changes")
+             .long("include-cosmetic-changes")
+             .takes_value(false).required(false)
+             .action(clap::ArgAction::SetTrue)
+             .requires("normalise")
+             .help("With --normalise, still include changes which are the same after normalisation. See the cosmetic_change column")
+             )
+        .arg(Arg::new("split-values")
+             .long("split-values")
+             .value_name("KEY")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Treat values of this key as ; separated lists, with one line per item added/removed. Same format as -k (e.g. `payment:*`, or `*` for all keys). Can be specified multiple times")
+             )
+        .arg(Arg::new("detect-renames")
+             .long("detect-renames")
+             .takes_value(false).required(false)
+             .action(clap::ArgAction::SetTrue)
+             .help("When a key is removed & another key with the same value is added in a version, show it as one change (a rename), instead of a removal & an addition. Only if no other removed/added key has that value, and it's not yes/no. See the old_key & new_key columns")
+             )
+        .arg(Arg::new("lifecycle")
+             .long("lifecycle")
+             .takes_value(false).required(false)
+             .action(clap::ArgAction::SetTrue)
+             .help("Treat lifecycle prefixed keys (e.g. disused:shop) as states of the base key (shop)")
+             .long_help("Treat lifecycle prefixed keys (e.g. disused:shop) as states of the base key (shop). -k, -t, --exclude-key & --exclude-tag also match the prefixed keys (e.g. -k shop includes disused:shop), and when a key is replaced by another key with the same base key in a version (e.g. shop=bakery → disused:shop=bakery), it's one change, like --detect-renames. See the lifecycle_from & lifecycle_to columns. Lifecycle prefixes: disused, abandoned, was, demolished, removed, razed, destroyed, construction, proposed, planned")
+             )
+        .arg(Arg::new("change-kind")
+             .long("change-kind")
+             .value_name("add,remove,modify")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include these kinds of tag changes (comma separated): add, remove, modify")
+             .long_help("Only include these kinds of tag changes (comma separated, can be specified multiple times)\nadd = the tag was added (tag_count_delta +1)\nremove = the tag was removed (tag_count_delta -1)\nmodify = the value was changed (tag_count_delta 0)")
+             )
+        .arg(Arg::new("from")
+             .long("from")
+             .value_name("KEY=VALUE")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes where the old value of KEY was VALUE (can be specified multiple times). VALUE * means any value")
+             )
+        .arg(Arg::new("to")
+             .long("to")
+             .value_name("KEY=VALUE")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes where the new value of KEY is VALUE (can be specified multiple times). VALUE * means any value")
+             )
+        .arg(Arg::new("exclude-key")
+             .long("exclude-key")
+             .value_name("KEY")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Exclude changes to this tag key (can be specified multiple times). Same format as -k")
+             )
+        .arg(Arg::new("exclude-tag")
+             .long("exclude-tag")
+             .value_name("KEY=VALUE")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Exclude changes with this KEY & VALUE (can be specified multiple times)")
+             )
+        .arg(Arg::new("exclude-keys-file")
+             .long("exclude-keys-file")
+             .value_name("FILENAME")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Exclude changes to the keys in this file (one per line, like --exclude-key). Lines starting with # are ignored")
+             )
+
+
+        .arg(Arg::new("member-changes")
+             .long("member-changes")
+             .takes_value(false).required(false)
+             .action(clap::ArgAction::SetTrue)
+             .help("Also include changes to way nodes & relation members")
+             .long_help("Also include changes to the nodes of a way, and the members of a relation.\nA node added to/removed from a way has the key `@nodes`, and the node id (e.g. `n123`) as the value.\nA relation member which is added/removed/has its role changed has the key `@members`, and the member & role (e.g. `w123=outer`) as the value.")
+             )
+
+        .arg(Arg::new("deletions")
+             .long("deletions")
+             .takes_value(false).required(false)
+             .action(clap::ArgAction::SetTrue)
+             .help("Also include one row for every deletion, with the key `@deleted`")
+             .long_help("Also include one row for every deleted object version, even if the object had no tags. It has the key `@deleted` & the new value `yes`.")
+             )
+        .arg(Arg::new("filter")
+             .short('e').long("filter")
+             .value_name("EXPRESSION")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes which match this osmium tags-filter style expression (can be specified multiple times)")
+             .long_help("Only include changes which match this expression (can be specified multiple times). Format: [!][TYPES/]KEY[OP VALUE]
+    TYPES: some of nwr, e.g. `w/highway` only matches ways
+    KEY: a key (with * & rawkey: like -k), or a regex between /, e.g. `/^name:/`
+    OP VALUE: `=V1,V2` (old or new value is one of these), `!=V1,V2` (neither value is), `~REGEX` (old or new value matches), `!~REGEX` (neither value matches)
+    A leading ! excludes changes which match, e.g. `!note` or `!w/highway=footway`
+Changes are included if they match any expression, and none of the excluded ones.")
+             )
+        .arg(Arg::new("filter-file")
+             .long("filter-file")
+             .value_name("FILENAME")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Read filter expressions (like --filter) from this file, one per line. Lines starting with # are ignored")
+             )
 
+        .arg(Arg::new("object-has")
+             .long("object-has")
+             .value_name("EXPRESSION")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes to objects which have a tag matching this expression (like --filter)")
+             .long_help("Only include (all) changes to objects which have a tag matching this expression (can be specified multiple times). Same format as --filter, e.g. `--object-has shop` includes all tag changes to shops. See --object-has-in for which versions are checked.")
+             )
+        .arg(Arg::new("object-has-in")
+             .long("object-has-in")
+             .takes_value(true).required(false)
+             .value_parser(["either", "ever", "latest"])
+             .default_value("either")
+             .help("Which version of the object must match --object-has")
+             .long_help("Which version of the object must match --object-has\neither (default) = the old or new version of that change\never = any version of the object\nlatest = the last version of the object in the file\n`ever` & `latest` keep all the versions of an object in memory")
+             )
 
         .arg(Arg::new("changeset_filename")
              .long("changesets")
@@ -269,6 +1424,14 @@ fn main() -> Result<()> {
              .takes_value(true).required(false)
              .help("Filename of the changeset file")
              )
+        .arg(Arg::new("changeset-filter")
+             .long("changeset-filter")
+             .value_name("EXPRESSION")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes from changesets with a tag matching this expression (like --filter). Needs --changesets")
+             .long_help("Only include changes made in changesets which have a tag matching this expression (can be specified multiple times). Same format as --filter, e.g. `created_by~^iD`, `hashtags~#hotosm-project-1234` or `!comment~import`. Needs the changeset file (--changesets).")
+             )
 
         .arg(Arg::new("uid")
              .long("uid")
@@ -277,16 +1440,92 @@ fn main() -> Result<()> {
              .value_parser(value_parser!(u32))
              .help("Only include changes made by this OSM user (by userid)")
              )
-
-
+        .arg(Arg::new("uid-file")
+             .long("uid-file")
+             .value_name("FILENAME")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes made by the user ids in this file (one per line, like --uid)")
+             )
+        .arg(Arg::new("exclude-uid")
+             .long("exclude-uid")
+             .value_name("USERID")
+             .action(ArgAction::Append)
+             .value_parser(value_parser!(u32))
+             .help("Exclude changes made by this OSM user (by userid)")
+             )
+        .arg(Arg::new("exclude-uid-file")
+             .long("exclude-uid-file")
+             .value_name("FILENAME")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Exclude changes made by the user ids in this file (one per line, like --exclude-uid)")
+             )
+        .arg(Arg::new("changeset-id")
+             .long("changeset-id")
+             .value_name("CHANGESETID,...")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes made in these changesets (comma separated, can be specified multiple times)")
+             )
+        .arg(Arg::new("changeset-id-file")
+             .long("changeset-id-file")
+             .value_name("FILENAME")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes made in the changesets in this file (one per line)")
+             )
+        .arg(Arg::new("id")
+             .long("id")
+             .value_name("ID,...")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes to these objects, e.g. n123,w456 (comma separated, can be specified multiple times). A plain number is a node id")
+             )
+        .arg(Arg::new("id-file")
+             .long("id-file")
+             .value_name("FILENAME")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes to the objects in this file (one per line, like the id column & osmium getid)")
+             )
+        .arg(Arg::new("user")
+             .long("user")
+             .value_name("USERNAME")
+             .takes_value(true).required(false)
+             .multiple(true).number_of_values(1)
+             .help("Only include changes made by this OSM user (by username on that version)")
+             )
+
+
         .arg(Arg::new("output_format")
              .long("output-format")
              .takes_value(true).required(false)
-             .help("output format")
-             .possible_values(["auto", "csv", "tsv"])
+             .help("output format. auto = from the output filename (.csv, .tsv, .geojsonl, .geojsonseq, .geojsons)")
+             .possible_values(["auto", "csv", "tsv", "geojsonseq"])
              .hidden_short_help(true)
              .default_value("auto")
              )
+        .arg(Arg::new("escape")
+             .long("escape")
+             .takes_value(true).required(false)
+             .possible_values(["backslash", "rfc4180", "none"])
+             .default_value("backslash")
+             .help("How to write special characters (e.g. newlines) in strings")
+             .long_help("How to write special characters in strings (e.g. tag values, usernames).\nbackslash = \\, tab, newline & carriage return are written as \\\\, \\t, \\n & \\r, so every record is one line\nrfc4180 = written as is, fields are \"quoted\" if needed (RFC 4180 CSV)\nnone = written as is, with no quoting (unless --quote-style)")
+             )
+        .arg(Arg::new("delimiter")
+             .long("delimiter")
+             .value_name("CHAR")
+             .takes_value(true).required(false)
+             .help("Field delimiter (e.g. `;` or `|`, `tab` for a tab). Default: , for CSV, tab for TSV")
+             )
+        .arg(Arg::new("quote_style")
+             .long("quote-style")
+             .takes_value(true).required(false)
+             .possible_values(["necessary", "always", "non-numeric", "never"])
+             .help("When to put \"quotes\" around fields. Default: necessary (never with --escape none)")
+             )
 
         .arg(Arg::new("columns")
              .short('C').long("columns")
@@ -310,10 +1549,51 @@ fn main() -> Result<()> {
     uid: UID of new object.
     changeset_id: Changeset ID of the new object
     changeset.TAG: TAG of the changeset 
+    tag.KEY: Value of the KEY tag of the new object
+    old_tag.KEY: Value of the KEY tag of the old object
+    all_tags_json: All the tags of the new object, as a JSON object
+    old_all_tags_json: All the tags of the old object, as a JSON object
+    cosmetic_change: yes if the old & new values are the same after --normalise, else no
+    lat, lon: Location of the new version (for ways & relations: the centroid, if there is a location store)
+    old_lat, old_lon: Location of the old version (as lat & lon)
+    point: Location of the new version as WKT, e.g. POINT(2.25 1.5)
+    centroid_lat, centroid_lon: Average location of the nodes of the new version (uses a location store)
+    bbox: Bounding box of the new version, as min_lon,min_lat,max_lon,max_lat (uses a location store)
+    region: Id of the region(s) from --regions which the new version (lat & lon) is in, ; separated (uses a location store)
     tag_count_delta: What is the totaly change to the number
+    member_count_delta: Change in the number of way nodes/relation members (empty for nodes)
+    added_keys, removed_keys, modified_keys: ; separated keys changed in this version (only with --line-type version)
+    n_added_keys, n_removed_keys, n_modified_keys: Number of keys added/removed/modified (only with --line-type version)
+    n_tags_before, n_tags_after: Number of tags on the old & new version
+    first_datetime, last_datetime, n_nodes, n_ways, n_relations: For --line-type changeset
+    change_kind: What happened to the object: create, modify, delete, undelete
+    old_username, old_uid, old_datetime, old_changeset_id: As above, for the previous version
+    seconds_since_previous_version: Seconds between the previous & new version
+    value_author_{version,username,uid,datetime,changeset_id}: As above, for the version which set the old value
                 ")
              )
 
+        .arg(Arg::new("location-store")
+             .long("location-store")
+             .value_name("FILENAME.db")
+             .takes_value(true).required(false)
+             .help("Store the history of node locations & way nodes in this SQLite file, so ways & relations have locations. Default: a temporary file, if needed for the columns")
+             )
+
+        .arg(Arg::new("regions")
+             .long("regions")
+             .value_name("FILENAME.geojson")
+             .takes_value(true).required(false)
+             .help("GeoJSON file of (multi)polygons (e.g. countries) for the region column")
+             )
+        .arg(Arg::new("region-property")
+             .long("region-property")
+             .value_name("PROPERTY")
+             .takes_value(true).required(false)
+             .default_value("id")
+             .help("Which property of the --regions features to use as the region id (the feature's id is used if it doesn't have this property)")
+             )
+
         .arg(Arg::new("object-types")
              .short('T').long("object-types")
              .value_name("[nwr]")
@@ -326,8 +1606,32 @@ fn main() -> Result<()> {
         .arg(Arg::new("line-type")
              .long("line-type")
              .takes_value(true)
-             .value_parser(["oldnew", "separate"])
+             .value_parser(["oldnew", "separate", "version", "changeset", "transition", "latest"])
              .default_value("oldnew")
+             .help("What each line is")
+             .long_help("What each line is:\noldnew (default) = one line per changed tag, with the old & new value\nseparate = one line for the old value & one line for the new value (also used if the value_count_delta column is used)\nversion = one line per changed object version, with the added_keys, removed_keys, modified_keys, etc. columns. The default columns are then: id,new_version,old_version,iso_datetime,username,uid,changeset_id,added_keys,removed_keys,modified_keys,n_tags_before,n_tags_after\nchangeset = one line per changeset (at the end), with the added_keys etc. columns as KEY=COUNT (see --top-keys). Only some columns can be used. The default columns are then: changeset_id,uid,username,first_datetime,last_datetime,n_nodes,n_ways,n_relations,tag_count_delta,added_keys,removed_keys,modified_keys\ntransition = one line per key & old value → new value (at the end), with how many times it happened (count column). See --time-bucket & --top-transitions. Only the key, old_value, new_value, period & count columns can be used, they are the default columns\nlatest = one line per tag of the last version of each object, with the value_author_* columns for which version set that value. The default columns are then: id,new_version,iso_datetime,key,new_value,value_author_version,value_author_datetime,value_author_username,value_author_uid,value_author_changeset_id")
+             )
+        .arg(Arg::new("top-keys")
+             .long("top-keys")
+             .value_name("N")
+             .takes_value(true).required(false)
+             .value_parser(value_parser!(usize))
+             .default_value("10")
+             .help("With --line-type changeset, how many keys to include in the added_keys, removed_keys & modified_keys columns (0 = all)")
+             )
+        .arg(Arg::new("time-bucket")
+             .long("time-bucket")
+             .takes_value(true).required(false)
+             .value_parser(["year", "month", "day"])
+             .help("With --line-type transition, count the transitions per year/month/day (period column)")
+             )
+        .arg(Arg::new("top-transitions")
+             .long("top-transitions")
+             .value_name("N")
+             .takes_value(true).required(false)
+             .value_parser(value_parser!(usize))
+             .default_value("0")
+             .help("With --line-type transition, only include the N most common transitions of each key (& period) (0 = all)")
              )
 
 
@@ -353,6 +1657,19 @@ fn main() -> Result<()> {
         osmio::pbf::PBFReader::new(BufReader::new(ReaderWithSize::from_file(file)?));
     let mut objects_iter = osm_obj_reader.objects();
 
+    let normalisation: Normalisation = match matches.get_one::<String>("normalise") {
+        None => Normalisation::default(),
+        Some(modes) => modes.parse()?,
+    };
+    if normalisation.is_enabled() {
+        info!("Normalising tag values: {:?}", normalisation);
+    }
+    let include_cosmetic_changes = matches.get_flag("include-cosmetic-changes");
+    let normalise_kv = |(k, v): (SmolStr, SmolStr)| -> (SmolStr, SmolStr) {
+        let v = normalisation.normalise(&v).into();
+        (k, v)
+    };
+
     let only_include_keys: SmallVec<[KeyFilter; 2]> = matches
         .get_many::<String>("key")
         .into_iter()
@@ -361,17 +1678,139 @@ fn main() -> Result<()> {
         .collect();
 
     let only_include_tags: SmallVec<[(SmolStr, SmolStr); 2]> = matches
-        .get_many("tag")
+        .get_many::<String>("tag")
         .into_iter()
         .flatten()
-        .map(|kv: &String| {
-            let mut parts = kv.splitn(2, "=").map(SmolStr::from);
-            (parts.next().unwrap(), parts.next().unwrap())
-        })
+        .map(|kv| parse_kv(kv).map(normalise_kv))
+        .collect::<Result<_>>()?;
+
+    let only_include_change_kinds = match matches.get_many::<String>("change-kind") {
+        None => (true, true, true),
+        Some(vals) => {
+            let mut change_kinds = (false, false, false);
+            for change_kind in vals.flat_map(|v| v.split(',')) {
+                match change_kind.trim() {
+                    "add" | "added" => change_kinds.0 = true,
+                    "remove" | "removed" => change_kinds.1 = true,
+                    "modify" | "modified" => change_kinds.2 = true,
+                    other => bail!(
+                        "Unknown change kind {:?}, must be add, remove or modify",
+                        other
+                    ),
+                }
+            }
+            info!(
+                "Only including (added, removed, modified) changes: {:?}",
+                change_kinds
+            );
+            change_kinds
+        }
+    };
+
+    let only_include_from: SmallVec<[(SmolStr, SmolStr); 1]> = matches
+        .get_many::<String>("from")
+        .into_iter()
+        .flatten()
+        .map(|kv| parse_kv(kv).map(normalise_kv))
+        .collect::<Result<_>>()?;
+    let only_include_to: SmallVec<[(SmolStr, SmolStr); 1]> = matches
+        .get_many::<String>("to")
+        .into_iter()
+        .flatten()
+        .map(|kv| parse_kv(kv).map(normalise_kv))
+        .collect::<Result<_>>()?;
+    if !only_include_from.is_empty() || !only_include_to.is_empty() {
+        info!(
+            "Only including changes from {:?} to {:?}",
+            only_include_from, only_include_to
+        );
+    }
+
+    let mut exclude_keys: SmallVec<[KeyFilter; 2]> = matches
+        .get_many::<String>("exclude-key")
+        .into_iter()
+        .flatten()
+        .map(|s: &String| KeyFilter::from_str(s).unwrap())
         .collect();
+    for filename in matches
+        .get_many::<String>("exclude-keys-file")
+        .into_iter()
+        .flatten()
+    {
+        exclude_keys.extend(read_keys_file(filename)?);
+    }
+
+    let exclude_tags: SmallVec<[(SmolStr, SmolStr); 2]> = matches
+        .get_many::<String>("exclude-tag")
+        .into_iter()
+        .flatten()
+        .map(|kv| parse_kv(kv).map(normalise_kv))
+        .collect::<Result<_>>()?;
 
-    let only_include_uids: Option<SmallVec<[u32; 1]>> =
+    let mut only_include_uids: Option<HashSet<u32>> =
         matches.get_many("uid").map(|vals| vals.copied().collect());
+    for filename in matches.get_many::<String>("uid-file").into_iter().flatten() {
+        only_include_uids
+            .get_or_insert_with(HashSet::new)
+            .extend(read_ids_file::<u32>(filename)?);
+    }
+
+    let mut exclude_uids: HashSet<u32> = matches
+        .get_many::<u32>("exclude-uid")
+        .into_iter()
+        .flatten()
+        .copied()
+        .collect();
+    for filename in matches
+        .get_many::<String>("exclude-uid-file")
+        .into_iter()
+        .flatten()
+    {
+        exclude_uids.extend(read_ids_file::<u32>(filename)?);
+    }
+
+    let mut only_include_changesets: Option<HashSet<u32>> = matches
+        .get_many::<String>("changeset-id")
+        .map(|vals| {
+            vals.flat_map(|v| v.split(','))
+                .map(|cid| {
+                    cid.trim()
+                        .parse()
+                        .with_context(|| format!("Invalid changeset id {:?}", cid))
+                })
+                .collect::<Result<_>>()
+        })
+        .transpose()?;
+    for filename in matches
+        .get_many::<String>("changeset-id-file")
+        .into_iter()
+        .flatten()
+    {
+        only_include_changesets
+            .get_or_insert_with(HashSet::new)
+            .extend(read_ids_file::<u32>(filename)?);
+    }
+
+    let mut only_include_ids: Option<HashSet<ObjectId>> = matches
+        .get_many::<String>("id")
+        .map(|vals| {
+            vals.flat_map(|v| v.split(','))
+                .map(ObjectId::from_str)
+                .collect::<Result<_>>()
+        })
+        .transpose()?;
+    for filename in matches.get_many::<String>("id-file").into_iter().flatten() {
+        only_include_ids
+            .get_or_insert_with(HashSet::new)
+            .extend(read_ids_file::<ObjectId>(filename)?);
+    }
+
+    let only_include_users: SmallVec<[SmolStr; 1]> = matches
+        .get_many::<String>("user")
+        .into_iter()
+        .flatten()
+        .map(SmolStr::from)
+        .collect();
 
     let only_include_types =
         matches
@@ -385,19 +1824,56 @@ fn main() -> Result<()> {
                 )
             });
 
-    let columns: SmallVec<[Column; 12]> = matches
-        .get_one::<String>("columns")
-        .map(String::as_str)
-        .unwrap()
-        .split(',')
+    let line_type_arg = matches.get_one::<String>("line-type").unwrap().as_str();
+    let columns: SmallVec<[Column; 12]> = match line_type_arg {
+        "version" if matches.occurrences_of("columns") == 0 => {
+            "id,new_version,old_version,iso_datetime,username,uid,changeset_id,added_keys,removed_keys,modified_keys,n_tags_before,n_tags_after"
+        }
+        "changeset" if matches.occurrences_of("columns") == 0 => {
+            "changeset_id,uid,username,first_datetime,last_datetime,n_nodes,n_ways,n_relations,tag_count_delta,added_keys,removed_keys,modified_keys"
+        }
+        "latest" if matches.occurrences_of("columns") == 0 => {
+            "id,new_version,iso_datetime,key,new_value,value_author_version,value_author_datetime,value_author_username,value_author_uid,value_author_changeset_id"
+        }
+        "transition" if matches.occurrences_of("columns") == 0 => {
+            if matches.contains_id("time-bucket") {
+                "period,key,old_value,new_value,count"
+            } else {
+                "key,old_value,new_value,count"
+            }
+        }
+        _ => matches.get_one::<String>("columns").unwrap(),
+    }
+    .split(',')
         .map(|col_str| col_str.parse())
         .collect::<Result<_>>()?;
     debug!("columns: {:?}", columns);
 
-    let line_type = if columns.contains(&Column::ValueCountDelta) {
-        LineType::SeparateLines
-    } else {
-        LineType::OldNewValue
+    let track_value_authors = columns.iter().any(Column::is_value_author);
+
+    let line_type = match line_type_arg {
+        "version" => LineType::Version,
+        "changeset" => {
+            if let Some(column) = columns.iter().find(|c| !c.is_changeset_summary()) {
+                bail!(
+                    "The {} column can't be used with --line-type changeset",
+                    column.header()
+                );
+            }
+            LineType::Changeset
+        }
+        "transition" => {
+            if let Some(column) = columns.iter().find(|c| !c.is_transition_summary()) {
+                bail!(
+                    "The {} column can't be used with --line-type transition",
+                    column.header()
+                );
+            }
+            LineType::Transition
+        }
+        "latest" => LineType::Latest,
+        _ if columns.contains(&Column::ValueCountDelta) => LineType::SeparateLines,
+        _ => LineType::OldNewValue,
     };
 
     if !only_include_tags.is_empty() {
@@ -414,11 +1890,165 @@ fn main() -> Result<()> {
             only_include_keys
         );
     }
+    if !exclude_keys.is_empty() {
+        info!(
+            "Excluding changes to these {} keys(s): {:?}",
+            exclude_keys.len(),
+            exclude_keys
+        );
+    }
+    if !exclude_tags.is_empty() {
+        info!(
+            "Excluding changes with these {} tag(s): {:?}",
+            exclude_tags.len(),
+            exclude_tags
+        );
+    }
+
+    let mut expressions: Vec<TagExpression> = matches
+        .get_many::<String>("filter")
+        .into_iter()
+        .flatten()
+        .map(|s| s.parse())
+        .collect::<Result<_>>()?;
+    for filename in matches
+        .get_many::<String>("filter-file")
+        .into_iter()
+        .flatten()
+    {
+        expressions.extend(read_expressions_file(filename)?);
+    }
+    if !expressions.is_empty() {
+        info!(
+            "Filtering with these {} expression(s): {:?}",
+            expressions.len(),
+            expressions
+        );
+    }
+    let (exclude_expressions, include_expressions) =
+        expressions.into_iter().partition(|e| e.negated);
+
+    let lifecycle = matches.get_flag("lifecycle");
+    if lifecycle {
+        info!("Treating lifecycle prefixes as states of the base key");
+    }
+    let tag_filter = TagFilter {
+        only_include_keys,
+        only_include_tags,
+        exclude_keys,
+        exclude_tags,
+        only_include_change_kinds,
+        only_include_from,
+        only_include_to,
+        include_expressions,
+        exclude_expressions,
+        normalisation,
+        lifecycle,
+    };
+
+    let split_value_keys: SmallVec<[KeyFilter; 2]> = matches
+        .get_many::<String>("split-values")
+        .into_iter()
+        .flatten()
+        .map(|s: &String| KeyFilter::from_str(s).unwrap())
+        .collect();
+    if !split_value_keys.is_empty() {
+        info!(
+            "Splitting values on ; for these key(s): {:?}",
+            split_value_keys
+        );
+    }
+
+    let detect_renames = matches.get_flag("detect-renames");
+    if detect_renames {
+        info!("Detecting key renames");
+    }
+
+    let include_member_changes = matches.get_flag("member-changes");
+    if include_member_changes {
+        info!("Including changes to way nodes & relation members");
+    }
+    let object_filter = match matches.get_many::<String>("object-has") {
+        None => None,
+        Some(vals) => {
+            let expressions: Vec<TagExpression> = vals.map(|s| s.parse()).collect::<Result<_>>()?;
+            let mode = match matches
+                .get_one::<String>("object-has-in")
+                .map(String::as_str)
+            {
+                Some("either") => ObjectFilterMode::EitherVersion,
+                Some("ever") => ObjectFilterMode::Ever,
+                Some("latest") => ObjectFilterMode::Latest,
+                _ => unreachable!(),
+            };
+            info!(
+                "Only including objects which have these {} tag(s) in {:?}: {:?}",
+                expressions.len(),
+                mode,
+                expressions
+            );
+            let (exclude_expressions, include_expressions) =
+                expressions.into_iter().partition(|e| e.negated);
+            Some(ObjectFilter {
+                include_expressions,
+                exclude_expressions,
+                mode,
+            })
+        }
+    };
+    // Other modes need all the versions of an object before processing it
+    let buffer_history = object_filter
+        .as_ref()
+        .is_some_and(|f| f.mode != ObjectFilterMode::EitherVersion)
+        || matches!(line_type, LineType::Latest);
+
+    let include_deletions = matches.get_flag("deletions");
+    if include_deletions {
+        info!("Including a row for every deletion");
+    }
+    if matches!(line_type, LineType::Latest) {
+        ensure!(
+            !include_member_changes,
+            "--member-changes can't be used with --line-type latest"
+        );
+        ensure!(
+            !include_deletions,
+            "--deletions can't be used with --line-type latest"
+        );
+    }
 
     if let Some(only_include_uids) = only_include_uids.as_ref() {
         info!(
-            "Only including changes made by user id {:?}",
-            only_include_uids
+            "Only including changes made by {} user id(s)",
+            only_include_uids.len()
+        );
+        debug!("user ids: {:?}", only_include_uids);
+    }
+    if !exclude_uids.is_empty() {
+        info!(
+            "Excluding changes made by {} user id(s)",
+            exclude_uids.len()
+        );
+        debug!("excluded user ids: {:?}", exclude_uids);
+    }
+    if let Some(only_include_changesets) = only_include_changesets.as_ref() {
+        info!(
+            "Only including changes made in {} changeset(s)",
+            only_include_changesets.len()
+        );
+        debug!("changeset ids: {:?}", only_include_changesets);
+    }
+    if let Some(only_include_ids) = only_include_ids.as_ref() {
+        info!(
+            "Only including changes to {} object(s)",
+            only_include_ids.len()
+        );
+        debug!("object ids: {:?}", only_include_ids);
+    }
+    if !only_include_users.is_empty() {
+        info!(
+            "Only including changes made by username(s) {:?}",
+            only_include_users
         );
     }
 
@@ -437,13 +2067,6 @@ fn main() -> Result<()> {
         None
     };
 
-    let include_header = match (matches.get_flag("header"), matches.get_flag("no-header")) {
-        (false, false) => true,
-        (true, false) => true,
-        (false, true) => false,
-        (true, true) => unreachable!(),
-    };
-
     let output_format = match (
         matches.get_one("output_format").map(String::as_str),
         matches.get_one("output").map(String::as_str),
@@ -462,12 +2085,80 @@ fn main() -> Result<()> {
         {
             OutputFormat::TSV
         }
+        (Some("geojsonseq"), _) => OutputFormat::GeoJSONSeq,
+        (Some("auto"), Some(filename))
+            if GEOJSONSEQ_EXTENSIONS.iter().any(|ext| {
+                filename.ends_with(ext) || filename.ends_with(&format!("{}.gz", ext))
+            }) =>
+        {
+            OutputFormat::GeoJSONSeq
+        }
         (format, filename) => unreachable!(
             "Unable to determine output format: format={:?} filename={:?}",
             format, filename
         ),
     };
 
+    let location_store = match matches.get_one::<String>("location-store") {
+        Some(filename) => {
+            info!("Storing node locations in {}", filename);
+            Some(LocationStore::new(filename)?)
+        }
+        None if columns.iter().any(Column::needs_location_store)
+            || (matches!(output_format, OutputFormat::GeoJSONSeq)
+                && (only_include_types.1 || only_include_types.2)) =>
+        {
+            info!("Storing node locations in a temporary file");
+            // An empty filename is a temporary file, which is deleted at the end
+            Some(LocationStore::new("")?)
+        }
+        None => None,
+    };
+
+    let region_lookup = match matches.get_one::<String>("regions") {
+        Some(filename) => Some(RegionLookup::from_filename(
+            filename,
+            matches.get_one::<String>("region-property").unwrap(),
+        )?),
+        None => {
+            ensure!(
+                !columns.contains(&Column::Region),
+                "The region column needs a regions file (--regions)"
+            );
+            None
+        }
+    };
+
+    let changeset_filter = match matches.get_many::<String>("changeset-filter") {
+        None => None,
+        Some(vals) => {
+            let expressions: Vec<TagExpression> = vals.map(|s| s.parse()).collect::<Result<_>>()?;
+            let changeset_filename = matches
+                .get_one::<String>("changeset_filename")
+                .context("--changeset-filter needs a changeset file (--changesets)")?;
+            info!(
+                "Only including changes from changesets with these {} tag(s): {:?}",
+                expressions.len(),
+                expressions
+            );
+            let (exclude_expressions, include_expressions) =
+                expressions.into_iter().partition(|e| e.negated);
+            Some(ChangesetFilter {
+                lookup: ChangesetTagLookup::from_filename(changeset_filename)?,
+                include_expressions,
+                exclude_expressions,
+                last_result: None,
+            })
+        }
+    };
+
+    let include_header = match (matches.get_flag("header"), matches.get_flag("no-header")) {
+        (false, false) => true,
+        (true, false) => true,
+        (false, true) => false,
+        (true, true) => unreachable!(),
+    };
+
     let output_path = matches.get_one::<String>("output").unwrap();
     let output_writer: Box<dyn std::io::Write> = if output_path == "-" {
         Box::new(std::io::stdout())
@@ -487,6 +2178,18 @@ fn main() -> Result<()> {
                 // uncompressed
                 trace!("Output file ends with .[ct]sv so no compression");
                 output_writer
+            } else if GEOJSONSEQ_EXTENSIONS
+                .iter()
+                .any(|ext| output_path.ends_with(&format!("{}.gz", ext)))
+            {
+                trace!("Output file ends with .geojson*.gz so using regular gzip");
+                Box::new(GzEncoder::new(output_writer, Compression::default()))
+            } else if GEOJSONSEQ_EXTENSIONS
+                .iter()
+                .any(|ext| output_path.ends_with(ext))
+            {
+                trace!("Output file ends with .geojson* so no compression");
+                output_writer
             } else {
                 bail!(
                     "Cannot auto-detect output compression format: {:?}",
@@ -500,22 +2203,91 @@ fn main() -> Result<()> {
     };
     let mut output = csv::WriterBuilder::new();
     match output_format {
-        OutputFormat::CSV => {}
+        OutputFormat::CSV | OutputFormat::GeoJSONSeq => {}
         OutputFormat::TSV => {
             output.delimiter(b'\t');
         }
     }
-    let mut output = output.from_writer(output_writer);
+    if let Some(delimiter) = matches.get_one::<String>("delimiter") {
+        let delimiter = match delimiter.as_str() {
+            "tab" | "\\t" => b'\t',
+            d if d.len() == 1 => d.as_bytes()[0],
+            d => bail!("Delimiter must be one (ASCII) character, not {:?}", d),
+        };
+        output.delimiter(delimiter);
+    }
+    let escape = match matches.get_one::<String>("escape").map(String::as_str) {
+        // JSON has it's own escaping
+        _ if matches!(output_format, OutputFormat::GeoJSONSeq) => Escape::None,
+        Some("backslash") => Escape::Backslash,
+        Some("rfc4180") => Escape::Rfc4180,
+        Some("none") => Escape::None,
+        _ => unreachable!(),
+    };
+    output.quote_style(
+        match matches.get_one::<String>("quote_style").map(String::as_str) {
+            Some("necessary") => csv::QuoteStyle::Necessary,
+            Some("always") => csv::QuoteStyle::Always,
+            Some("non-numeric") => csv::QuoteStyle::NonNumeric,
+            Some("never") => csv::QuoteStyle::Never,
+            None if escape == Escape::None => csv::QuoteStyle::Never,
+            _ => csv::QuoteStyle::Necessary,
+        },
+    );
+    let output = match output_format {
+        OutputFormat::CSV | OutputFormat::TSV => RowOutput::Csv(output.from_writer(output_writer)),
+        OutputFormat::GeoJSONSeq => RowOutput::GeoJSONSeq {
+            output: std::io::BufWriter::new(output_writer),
+            properties: String::new(),
+        },
+    };
+
+    let mut row_writer = RowWriter::new(
+        output,
+        columns,
+        line_type,
+        changeset_lookup,
+        location_store,
+        region_lookup,
+        escape,
+    );
+    row_writer.top_keys = *matches.get_one::<usize>("top-keys").unwrap();
+    row_writer.time_bucket = matches
+        .get_one::<String>("time-bucket")
+        .map(|b| match b.as_str() {
+            "year" => TimeBucket::Year,
+            "month" => TimeBucket::Month,
+            "day" => TimeBucket::Day,
+            _ => unreachable!(),
+        });
+    row_writer.top_transitions = *matches.get_one::<usize>("top-transitions").unwrap();
 
     if include_header {
         trace!("Writing CSV header");
-        for c in columns.iter() {
-            output.write_field(c.header().as_ref())?;
-        }
-
-        output.write_record(None::<&[u8]>)?;
+        row_writer.write_header()?;
     }
 
+    let mut processor = Processor {
+        only_include_uids,
+        exclude_uids,
+        only_include_users,
+        only_include_changesets,
+        only_include_ids,
+        only_include_types,
+        include_member_changes,
+        include_deletions,
+        tag_filter,
+        include_cosmetic_changes,
+        split_value_keys,
+        detect_renames,
+        object_filter,
+        changeset_filter,
+        track_value_authors,
+        value_authors: HashMap::new(),
+        row_writer,
+    };
+    let mut history: Vec<StringOSMObj> = Vec::new();
+
     let mut curr = objects_iter.next().unwrap();
     let mut last: Option<osmio::obj_types::StringOSMObj> = None;
 
@@ -523,11 +2295,7 @@ fn main() -> Result<()> {
 
     let mut time_counter = do_every::DoEvery::new();
 
-    let mut field_bytes = Vec::with_capacity(25);
-    let mut utf8_bytes_buffer = vec![0; 4];
     let started_processing = Instant::now();
-    let mut passes_uid_check;
-    let mut passes_type_check;
 
     loop {
         // Logging output
@@ -549,17 +2317,199 @@ fn main() -> Result<()> {
             num_objects = 1;
         }
 
-        passes_uid_check = if let (Some(this_uid), Some(only_include_uids)) =
-            (curr.uid(), only_include_uids.as_ref())
-        {
-            // We have uid's & we're filtering based on uids
-            only_include_uids.iter().any(|u| u == &this_uid)
+        if let Some(location_store) = processor.row_writer.location_store.as_mut() {
+            location_store.add(&curr)?;
+        }
+
+        if buffer_history {
+            if let Some(h) = history.last()
+                && (h.object_type() != curr.object_type() || h.id() != curr.id())
+            {
+                ensure!(
+                    sorted_objects(h, &curr) == Ordering::Less,
+                    "Non sorted input"
+                );
+                processor.process_history(&history)?;
+                history.clear();
+            }
+            history.push(curr);
         } else {
-            true
+            processor.process(&curr, last.as_ref())?;
+            last = Some(curr);
+        }
+
+        curr = match objects_iter.next() {
+            None => {
+                break;
+            }
+            Some(o) => o,
+        };
+    }
+    if buffer_history {
+        processor.process_history(&history)?;
+    }
+    if let Some(location_store) = processor.row_writer.location_store.as_mut() {
+        location_store.finish()?;
+    }
+    processor.row_writer.finish()?;
+
+    info!(
+        "Finished in {}",
+        format_time(&(Instant::now() - started_processing))
+    );
+    Ok(())
+}
+
+/// Decides which changes of each object version to include, and writes them
+struct Processor {
+    only_include_uids: Option<HashSet<u32>>,
+    exclude_uids: HashSet<u32>,
+    only_include_users: SmallVec<[SmolStr; 1]>,
+    only_include_changesets: Option<HashSet<u32>>,
+    only_include_ids: Option<HashSet<ObjectId>>,
+    only_include_types: (bool, bool, bool),
+    include_member_changes: bool,
+    include_deletions: bool,
+    tag_filter: TagFilter,
+    /// Include changes which only differ after normalisation
+    include_cosmetic_changes: bool,
+    /// Keys whose values are `;` separated lists, with a line per added/removed item
+    split_value_keys: SmallVec<[KeyFilter; 2]>,
+    /// Show a removed & added key with the same value as one rename change
+    detect_renames: bool,
+    object_filter: Option<ObjectFilter>,
+    changeset_filter: Option<ChangesetFilter>,
+    track_value_authors: bool,
+    /// For the current object, which version set the value of each tag
+    value_authors: HashMap<SmolStr, ValueAuthor>,
+    row_writer: RowWriter,
+}
+
+impl Processor {
+    /// Process all the versions of one object
+    fn process_history(&mut self, history: &[StringOSMObj]) -> Result<()> {
+        if let Some(object_filter) = self.object_filter.as_ref()
+            && !object_filter.history_matches(history)
+        {
+            return Ok(());
+        }
+        if let LineType::Latest = self.row_writer.line_type {
+            return self.process_latest(history);
+        }
+        for (i, curr) in history.iter().enumerate() {
+            self.process(curr, i.checked_sub(1).map(|j| &history[j]))?;
+        }
+        Ok(())
+    }
+
+    /// With `LineType::Latest`, write the tags of the last version of this object, with which
+    /// version set each value. The user & changeset filters (incl. `--changeset-filter`) are
+    /// checked against that version.
+    fn process_latest(&mut self, history: &[StringOSMObj]) -> Result<()> {
+        let mut prev = None;
+        for curr in history.iter() {
+            update_value_authors(&mut self.value_authors, prev, curr);
+            prev = Some(curr);
+        }
+        let Some((curr, earlier)) = history.split_last() else {
+            return Ok(());
         };
+        let prev = earlier.last();
 
-        passes_type_check = matches!(
-            (curr.object_type(), only_include_types),
+        let passes_type_check = matches!(
+            (curr.object_type(), self.only_include_types),
+            (OSMObjectType::Node, (true, _, _))
+                | (OSMObjectType::Way, (_, true, _))
+                | (OSMObjectType::Relation, (_, _, true))
+        );
+        let passes_id_check = self
+            .only_include_ids
+            .as_ref()
+            .is_none_or(|only_include_ids| {
+                only_include_ids.contains(&ObjectId(curr.object_type(), curr.id()))
+            });
+        if !(passes_type_check && passes_id_check) {
+            return Ok(());
+        }
+
+        let tags: BTreeMap<_, _> = curr.tags().collect();
+        for (key, value) in tags {
+            if !self.tag_filter.key_passes(key) {
+                continue;
+            }
+            let Some(author) = self.value_authors.get(key) else {
+                continue;
+            };
+            let passes_author_check = self
+                .only_include_uids
+                .as_ref()
+                .is_none_or(|uids| uids.contains(&author.uid))
+                && !self.exclude_uids.contains(&author.uid)
+                && (self.only_include_users.is_empty()
+                    || self.only_include_users.contains(&author.user))
+                && self
+                    .only_include_changesets
+                    .as_ref()
+                    .is_none_or(|changesets| changesets.contains(&author.changeset_id));
+            if !passes_author_check {
+                continue;
+            }
+            if let Some(changeset_filter) = self.changeset_filter.as_mut()
+                && !changeset_filter.changeset_matches(author.changeset_id, curr.object_type())?
+            {
+                continue;
+            }
+            // The value hasn't changed since `author` set it
+            let change = TagChange {
+                key,
+                last_value: value,
+                last_value_existed: true,
+                curr_value: value,
+                curr_value_exists: true,
+                last_value_author: Some(author),
+                is_cosmetic: false,
+                renamed_from: None,
+            };
+            if !self.tag_filter.change_passes(&change, curr.object_type()) {
+                continue;
+            }
+            self.row_writer.write_line(curr, prev, &change)?;
+        }
+        Ok(())
+    }
+
+    /// Process this version of an object. `last` is the object before it in the file.
+    fn process(&mut self, curr: &StringOSMObj, last: Option<&StringOSMObj>) -> Result<()> {
+        let passes_uid_check = if let (Some(this_uid), Some(only_include_uids)) =
+            (curr.uid(), self.only_include_uids.as_ref())
+        {
+            // We have uid's & we're filtering based on uids
+            only_include_uids.contains(&this_uid)
+        } else {
+            true
+        };
+        let passes_uid_check =
+            passes_uid_check && !curr.uid().is_some_and(|u| self.exclude_uids.contains(&u));
+        let passes_user_check = self.only_include_users.is_empty()
+            || curr
+                .user()
+                .is_some_and(|user| self.only_include_users.iter().any(|u| u == user));
+
+        let passes_changeset_check = match (curr.changeset_id(), &self.only_include_changesets) {
+            (Some(changeset_id), Some(only_include_changesets)) => {
+                only_include_changesets.contains(&changeset_id)
+            }
+            _ => true,
+        };
+        let passes_id_check = self
+            .only_include_ids
+            .as_ref()
+            .is_none_or(|only_include_ids| {
+                only_include_ids.contains(&ObjectId(curr.object_type(), curr.id()))
+            });
+
+        let passes_type_check = matches!(
+            (curr.object_type(), self.only_include_types),
             (OSMObjectType::Node, (true, _, _))
                 | (OSMObjectType::Way, (_, true, _))
                 | (OSMObjectType::Relation, (_, _, true))
@@ -567,30 +2517,55 @@ fn main() -> Result<()> {
 
         let has_tags = match last {
             None => curr.tagged(),
-            Some(ref l) => l.tagged() || curr.tagged(),
+            Some(l) => l.tagged() || curr.tagged(),
         };
-        let process_object = has_tags && passes_uid_check && passes_type_check;
+        // Untagged ways & relations can still have node/member changes
+        let has_members = self.include_member_changes && !curr.is_node();
+        // Untagged objects can still be deleted
+        let is_deletion = self.include_deletions && curr.deleted();
+        let passes_object_check = match self.object_filter {
+            Some(ref object_filter) if object_filter.mode == ObjectFilterMode::EitherVersion => {
+                object_filter.object_matches(curr)
+                    || last.is_some_and(|l| {
+                        l.object_type() == curr.object_type()
+                            && l.id() == curr.id()
+                            && object_filter.object_matches(l)
+                    })
+            }
+            // Other modes are checked in `process_history`
+            _ => true,
+        };
+        let mut process_object = (has_tags || has_members || is_deletion)
+            && passes_uid_check
+            && passes_user_check
+            && passes_changeset_check
+            && passes_id_check
+            && passes_type_check
+            && passes_object_check;
+        // Checked last, since it needs a database lookup
+        if process_object && let Some(changeset_filter) = self.changeset_filter.as_mut() {
+            process_object = changeset_filter
+                .changeset_matches(curr.changeset_id().unwrap(), curr.object_type())?;
+        }
 
         // The 'only_include_tags' could be checked here to speed it up
 
         if process_object {
-            let (last_tags, last_version) = match last {
-                None => (None, "".to_string()),
-                Some(ref last) => {
+            let prev = match last {
+                None => None,
+                Some(last) => {
                     ensure!(
-                        sorted_objects(last, &curr) == Ordering::Less,
+                        sorted_objects(last, curr) == Ordering::Less,
                         "Non sorted input"
                     );
                     if last.object_type() == curr.object_type() && last.id() == curr.id() {
-                        (
-                            Some(last.tags().collect::<HashMap<_, _>>()),
-                            last.version().unwrap().to_string(),
-                        )
+                        Some(last)
                     } else {
-                        (None, "".to_string())
+                        None
                     }
                 }
             };
+            let last_tags = prev.map(|last| last.tags().collect::<HashMap<_, _>>());
 
             let curr_tags: BTreeMap<_, _> = curr.tags().collect();
             let mut keys: Vec<_> = curr_tags.keys().collect();
@@ -599,6 +2574,17 @@ fn main() -> Result<()> {
             }
             keys.sort();
             keys.dedup();
+            let renames = match last_tags.as_ref() {
+                Some(last_tags) if self.detect_renames || self.tag_filter.lifecycle => {
+                    renamed_keys(
+                        last_tags,
+                        &curr_tags,
+                        self.detect_renames,
+                        self.tag_filter.lifecycle,
+                    )
+                }
+                _ => Vec::new(),
+            };
 
             let mut last_value: &str;
             let mut last_value_existed;
@@ -606,9 +2592,17 @@ fn main() -> Result<()> {
             let mut curr_value_exists;
 
             for key in keys.into_iter() {
+                // Renames are included at the new key
+                if renames.iter().any(|(old_key, _)| old_key == key) {
+                    continue;
+                }
+                let renamed_from = renames
+                    .iter()
+                    .find(|(_, new_key)| new_key == key)
+                    .map(|(old_key, _)| *old_key);
                 // Should we skip this tag?
-                if !only_include_keys.is_empty()
-                    && !only_include_keys.iter().any(|k| k.key_matches(key))
+                if !(self.tag_filter.key_passes(key)
+                    || renamed_from.is_some_and(|k| self.tag_filter.key_passes(k)))
                 {
                     continue;
                 }
@@ -627,284 +2621,2141 @@ fn main() -> Result<()> {
                     curr_value = "";
                     curr_value_exists = false;
                 };
-                if last_value == curr_value {
+                if let Some(old_key) = renamed_from {
+                    last_value = last_tags.as_ref().unwrap()[old_key];
+                    last_value_existed = true;
+                } else if last_value == curr_value {
                     continue;
                 }
-                //dbg!(key); dbg!(last_value); dbg!(curr_value);
-                //dbg!(&only_include_tags);
-                if !only_include_tags.is_empty()
-                    && !only_include_tags
-                        .iter()
-                        .any(|(k, v)| k == key && (v == last_value || v == curr_value))
+                let is_cosmetic = renamed_from.is_none()
+                    && last_value_existed
+                    && curr_value_exists
+                    && self.tag_filter.normalisation.is_enabled()
+                    && self.tag_filter.normalisation.normalise(last_value)
+                        == self.tag_filter.normalisation.normalise(curr_value);
+                if is_cosmetic && !self.include_cosmetic_changes {
+                    continue;
+                }
+                let change = TagChange {
+                    key,
+                    last_value,
+                    last_value_existed,
+                    curr_value,
+                    curr_value_exists,
+                    last_value_author: if last_value_existed {
+                        self.value_authors.get(renamed_from.unwrap_or(key))
+                    } else {
+                        None
+                    },
+                    is_cosmetic,
+                    renamed_from,
+                };
+                if renamed_from.is_none()
+                    && self.split_value_keys.iter().any(|k| k.key_matches(key))
                 {
+                    let normalisation = self.tag_filter.normalisation;
+                    for (item, added) in value_item_changes(last_value, curr_value, normalisation) {
+                        let item_change = TagChange {
+                            last_value: if added { "" } else { item },
+                            last_value_existed: !added,
+                            curr_value: if added { item } else { "" },
+                            curr_value_exists: added,
+                            ..change
+                        };
+                        if self
+                            .tag_filter
+                            .change_passes(&item_change, curr.object_type())
+                        {
+                            self.row_writer.write_change(curr, prev, &item_change)?;
+                        }
+                    }
+                    continue;
+                }
+                if !self.tag_filter.change_passes(&change, curr.object_type()) {
                     continue;
                 }
 
-                trace!(
-                    "Write tag change {} {:?} → {:?} ({}→{})",
-                    key, last_value, curr_value, last_value_existed, curr_value_exists,
-                );
-
-                let mut i: u8 = 0;
+                self.row_writer.write_change(curr, prev, &change)?;
+            }
 
-                loop {
-                    match (&line_type, i) {
-                        (LineType::OldNewValue, 0) => {}
-                        (LineType::OldNewValue, 1) => {
-                            break;
-                        }
-                        (LineType::OldNewValue, _) => {
-                            unreachable!()
-                        }
-                        (LineType::SeparateLines, 0) => {
-                            if !last_value_existed {
-                                i += 1;
-                                continue;
-                            }
-                        }
-                        (LineType::SeparateLines, 1) => {
-                            if !curr_value_exists {
-                                i += 1;
-                                continue;
-                            }
-                        }
-                        (LineType::SeparateLines, 2) => {
-                            break;
-                        }
-                        (LineType::SeparateLines, _) => {
-                            unreachable!()
-                        }
+            if self.include_member_changes {
+                for (key, last_value, curr_value) in member_changes(prev, curr) {
+                    if !self.tag_filter.key_passes(key) {
+                        continue;
+                    }
+                    let change = TagChange {
+                        key,
+                        last_value: last_value.as_deref().unwrap_or(""),
+                        last_value_existed: last_value.is_some(),
+                        curr_value: curr_value.as_deref().unwrap_or(""),
+                        curr_value_exists: curr_value.is_some(),
+                        last_value_author: None,
+                        is_cosmetic: false,
+                        renamed_from: None,
+                    };
+                    if !self.tag_filter.change_passes(&change, curr.object_type()) {
+                        continue;
                     }
 
-                    for column in columns.iter() {
-                        field_bytes.clear();
-                        match column {
-                            Column::Key => {
-                                encode_field(key, &mut field_bytes, &mut utf8_bytes_buffer);
-                            }
-                            Column::NewValue => {
-                                encode_field(curr_value, &mut field_bytes, &mut utf8_bytes_buffer);
-                            }
-                            Column::OldValue => {
-                                encode_field(last_value, &mut field_bytes, &mut utf8_bytes_buffer);
-                            }
-                            Column::Value => {
-                                encode_field(
-                                    match i {
-                                        0 => last_value,
-                                        1 => curr_value,
-                                        _ => unreachable!(),
-                                    },
-                                    &mut field_bytes,
-                                    &mut utf8_bytes_buffer,
-                                );
-                            }
-                            Column::Id => {
-                                field_bytes.extend(
-                                    format!("{:?}{}", curr.object_type(), curr.id())
-                                        .as_str()
-                                        .bytes(),
-                                );
-                            }
-                            Column::RawId => {
-                                field_bytes.extend(curr.id().to_string().as_str().bytes())
-                            }
-                            Column::NewVersion => {
-                                field_bytes.extend(curr.version().unwrap().to_string().bytes());
-                            }
-                            Column::OldVersion => {
-                                field_bytes.extend(last_version.as_str().bytes());
-                            }
-                            Column::IsoDatetime => {
-                                field_bytes.extend(
-                                    curr.timestamp().as_ref().unwrap().to_iso_string().bytes(),
-                                );
-                            }
-                            Column::EpochDatetime => {
-                                field_bytes.extend(
-                                    curr.timestamp()
-                                        .as_ref()
-                                        .unwrap()
-                                        .to_epoch_number()
-                                        .to_string()
-                                        .bytes(),
-                                );
-                            }
-                            Column::DatetimeFmt(_tz_name, tz, fmt) => {
-                                let datetime = chrono::DateTime::from_timestamp_secs(
-                                    curr.timestamp().as_ref().unwrap().to_epoch_number(),
-                                )
-                                .unwrap();
-                                let datetime = datetime.with_timezone(&tz);
+                    self.row_writer.write_change(curr, prev, &change)?;
+                }
+            }
 
-                                let res = datetime.format(fmt).to_string();
-                                field_bytes.extend(res.bytes());
-                            }
-                            Column::Username => {
-                                encode_field(
-                                    curr.user().unwrap(),
-                                    &mut field_bytes,
-                                    &mut utf8_bytes_buffer,
-                                );
-                            }
-                            Column::Uid => {
-                                field_bytes.extend(curr.uid().unwrap().to_string().bytes());
-                            }
-                            Column::ChangesetId => {
-                                field_bytes
-                                    .extend(curr.changeset_id().unwrap().to_string().bytes());
-                            }
-                            Column::ObjectTypeShort => {
-                                field_bytes.extend(match curr.object_type() {
-                                    OSMObjectType::Node => b"n",
-                                    OSMObjectType::Way => b"w",
-                                    OSMObjectType::Relation => b"r",
-                                });
-                            }
-                            Column::ObjectTypeLong => {
-                                field_bytes.extend(match curr.object_type() {
-                                    OSMObjectType::Node => b"node".iter(),
-                                    OSMObjectType::Way => b"way".iter(),
-                                    OSMObjectType::Relation => b"relation".iter(),
-                                });
-                            }
-                            Column::ChangesetTag(changeset_tag) => {
-                                match changeset_lookup
-                                    .as_ref()
-                                    .unwrap()
-                                    .tags(curr.changeset_id().unwrap())?
-                                {
-                                    None => {
-                                        trace!(
-                                            "No tags found for changeset {:?}",
-                                            curr.changeset_id()
-                                        );
-                                    }
-                                    Some(tags_for_changeset) => {
-                                        if let Some(v) = tags_for_changeset
-                                            .iter()
-                                            .filter_map(|(k, v)| {
-                                                if k == changeset_tag { Some(v) } else { None }
-                                            })
-                                            .next()
-                                        {
-                                            field_bytes.extend(v.bytes());
-                                        }
-                                    }
-                                }
-                            }
-                            Column::TagCountDelta => {
-                                field_bytes.extend(match (last_value_existed, curr_value_exists) {
-                                    (false, false) => unreachable!(),
-                                    (false, true) => b"+1".iter(),
-                                    (true, false) => b"-1".iter(),
-                                    (true, true) => b"0".iter(),
-                                });
-                            }
+            if is_deletion && self.tag_filter.key_passes("@deleted") {
+                let change = TagChange {
+                    key: "@deleted",
+                    last_value: "",
+                    last_value_existed: false,
+                    curr_value: "yes",
+                    curr_value_exists: true,
+                    last_value_author: None,
+                    is_cosmetic: false,
+                    renamed_from: None,
+                };
+                if self.tag_filter.change_passes(&change, curr.object_type()) {
+                    self.row_writer.write_change(curr, prev, &change)?;
+                }
+            }
 
-                            Column::ValueCountDelta => {
-                                field_bytes.extend(match i {
-                                    0 => b"-1".iter(),
-                                    1 => b"+1".iter(),
-                                    _ => unreachable!(),
-                                });
-                            }
-                        }
-                        output.write_field(&field_bytes)?;
-                    }
+            self.row_writer.finish_version(curr, prev)?;
+        }
+
+        if self.track_value_authors {
+            // Done for every object, since later versions might be included
+            let prev =
+                last.filter(|l| l.object_type() == curr.object_type() && l.id() == curr.id());
+            update_value_authors(&mut self.value_authors, prev, curr);
+        }
+
+        Ok(())
+    }
+}
 
-                    output.write_record(None::<&[u8]>)?;
+/// Where the lines are written to
+// There is only one of these, so the size doesn't matter
+#[allow(clippy::large_enum_variant)]
+enum RowOutput {
+    Csv(csv::Writer<Box<dyn std::io::Write>>),
+    /// One GeoJSON Point Feature per line, with the columns as properties
+    GeoJSONSeq {
+        output: std::io::BufWriter<Box<dyn std::io::Write>>,
+        /// Properties of the current line, as JSON object members (in column order)
+        properties: String,
+    },
+}
 
-                    i += 1;
+impl RowOutput {
+    fn write_field(&mut self, column: &Column, field_bytes: &[u8]) -> Result<()> {
+        match self {
+            RowOutput::Csv(output) => output.write_field(field_bytes)?,
+            RowOutput::GeoJSONSeq { properties, .. } => {
+                if !properties.is_empty() {
+                    properties.push(',');
                 }
+                properties.push_str(&serde_json::to_string(&column.header())?);
+                properties.push(':');
+                properties.push_str(&serde_json::to_string(&geojson_property(
+                    column,
+                    field_bytes,
+                ))?);
             }
         }
+        Ok(())
+    }
 
-        last = Some(curr);
-        curr = match objects_iter.next() {
-            None => {
-                break;
+    /// End this line. `location` (lat & lon) is the GeoJSON Point
+    fn write_record(&mut self, location: Option<(i32, i32)>) -> Result<()> {
+        match self {
+            RowOutput::Csv(output) => output.write_record(None::<&[u8]>)?,
+            RowOutput::GeoJSONSeq { output, properties } => {
+                let geometry = match location {
+                    Some((lat, lon)) => format!(
+                        r#"{{"type":"Point","coordinates":[{},{}]}}"#,
+                        format_coord(lon),
+                        format_coord(lat)
+                    ),
+                    None => "null".to_string(),
+                };
+                writeln!(
+                    output,
+                    r#"{{"type":"Feature","geometry":{},"properties":{{{}}}}}"#,
+                    geometry, properties
+                )?;
+                properties.clear();
             }
-            Some(o) => o,
-        };
+        }
+        Ok(())
     }
 
-    info!(
-        "Finished in {}",
-        format_time(&(Instant::now() - started_processing))
-    );
-    Ok(())
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            RowOutput::Csv(output) => output.flush()?,
+            RowOutput::GeoJSONSeq { output, .. } => output.flush()?,
+        }
+        Ok(())
+    }
 }
 
-fn encode_field(field: &str, bytes: &mut Vec<u8>, utf8_bytes_buffer: &mut [u8]) {
-    bytes.clear();
+/// The GeoJSON property value of this column. Empty values are `null`, & numeric columns are
+/// numbers (without the `+` of deltas)
+fn geojson_property(column: &Column, field_bytes: &[u8]) -> serde_json::Value {
+    let value = String::from_utf8_lossy(field_bytes);
+    if value.is_empty() {
+        return serde_json::Value::Null;
+    }
+    if column.is_numeric()
+        && let Ok(num) = value.trim_start_matches('+').parse::<serde_json::Number>()
+    {
+        return serde_json::Value::Number(num);
+    }
+    serde_json::Value::String(value.into_owned())
+}
 
-    for c in field.chars() {
-        if c == '\t' {
-            bytes.push(b'\\');
-            bytes.push(b't');
-        } else if c == '\n' {
-            bytes.push(b'\\');
-            bytes.push(b'n');
+/// Writes the tag changes to the output file, with the selected columns
+struct RowWriter {
+    output: RowOutput,
+    columns: SmallVec<[Column; 12]>,
+    line_type: LineType,
+    changeset_lookup: Option<ChangesetTagLookup>,
+    location_store: Option<LocationStore>,
+    region_lookup: Option<RegionLookup>,
+    escape: Escape,
+    /// The changes of the current version, for `LineType::Version`
+    version_summary: Option<VersionSummary>,
+    /// The changes in every changeset, for `LineType::Changeset`
+    changeset_summaries: BTreeMap<u32, ChangesetSummary>,
+    /// How many keys to include in the `*_keys` columns for `LineType::Changeset`
+    top_keys: usize,
+    /// How often each (period, key, old value, new value) happened, for `LineType::Transition`
+    transitions: HashMap<(SmolStr, SmolStr, SmolStr, SmolStr), u64>,
+    time_bucket: Option<TimeBucket>,
+    /// How many transitions to include per key & period for `LineType::Transition`
+    top_transitions: usize,
+    field_bytes: Vec<u8>,
+    utf8_bytes_buffer: Vec<u8>,
+}
+
+impl RowWriter {
+    fn new(
+        output: RowOutput,
+        columns: SmallVec<[Column; 12]>,
+        line_type: LineType,
+        changeset_lookup: Option<ChangesetTagLookup>,
+        location_store: Option<LocationStore>,
+        region_lookup: Option<RegionLookup>,
+        escape: Escape,
+    ) -> Self {
+        RowWriter {
+            output,
+            columns,
+            line_type,
+            changeset_lookup,
+            location_store,
+            region_lookup,
+            escape,
+            version_summary: None,
+            changeset_summaries: BTreeMap::new(),
+            top_keys: 10,
+            transitions: HashMap::new(),
+            time_bucket: None,
+            top_transitions: 0,
+            field_bytes: Vec::with_capacity(25),
+            utf8_bytes_buffer: vec![0; 4],
+        }
+    }
+
+    /// The location of this object version. Ways & relations need a location store
+    fn geometry(&mut self, obj: &StringOSMObj) -> Result<Option<Geometry>> {
+        if obj.object_type() == OSMObjectType::Node {
+            Ok(node_location(obj).map(|loc| Geometry::from_locations([loc]).unwrap()))
+        } else if let Some(location_store) = self.location_store.as_mut() {
+            location_store.geometry(obj)
         } else {
-            c.encode_utf8(utf8_bytes_buffer);
-            bytes.extend(&utf8_bytes_buffer[..c.len_utf8()]);
+            Ok(None)
         }
     }
-}
 
-fn sorted_objects(a: &impl OSMObj, b: &impl OSMObj) -> std::cmp::Ordering {
-    a.object_type()
-        .cmp(&b.object_type())
-        .then(a.id().cmp(&b.id()))
-        .then(a.version().cmp(&b.version()))
-}
+    fn write_header(&mut self) -> Result<()> {
+        // GeoJSON has no header
+        let RowOutput::Csv(output) = &mut self.output else {
+            return Ok(());
+        };
+        for c in self.columns.iter() {
+            output.write_field(c.header().as_ref())?;
+        }
 
-pub fn format_time(duration: &std::time::Duration) -> String {
-    let sec = duration.as_secs_f32().round() as u64;
-    if sec < 60 {
-        format!("{:2}s", sec)
-    } else {
-        let (min, sec) = (sec / 60, sec % 60);
-        if min < 60 {
-            format!("{:2}m{:02}s", min, sec)
+        output.write_record(None::<&[u8]>)?;
+        Ok(())
+    }
+
+    /// Write any remaining lines, & flush the output
+    fn finish(&mut self) -> Result<()> {
+        match self.line_type {
+            LineType::Changeset => self.write_changeset_summaries()?,
+            LineType::Transition => self.write_transitions()?,
+            _ => {}
+        }
+        self.output.flush()
+    }
+
+    /// With `LineType::Changeset`, write one line per changeset
+    fn write_changeset_summaries(&mut self) -> Result<()> {
+        let escape = self.escape;
+        for (changeset_id, summary) in std::mem::take(&mut self.changeset_summaries) {
+            for column in self.columns.iter() {
+                let field_bytes = &mut self.field_bytes;
+                field_bytes.clear();
+                match column {
+                    Column::ChangesetId => field_bytes.extend(changeset_id.to_string().bytes()),
+                    Column::Uid => {
+                        if let Some(uid) = summary.uid {
+                            field_bytes.extend(uid.to_string().bytes());
+                        }
+                    }
+                    Column::Username => {
+                        if let Some(user) = summary.user.as_ref() {
+                            encode_field(user, escape, field_bytes, &mut self.utf8_bytes_buffer);
+                        }
+                    }
+                    Column::FirstIsoDatetime | Column::IsoDatetime => {
+                        if let Some(t) = summary.first_timestamp.as_ref() {
+                            field_bytes.extend(t.to_iso_string().bytes());
+                        }
+                    }
+                    Column::LastIsoDatetime => {
+                        if let Some(t) = summary.last_timestamp.as_ref() {
+                            field_bytes.extend(t.to_iso_string().bytes());
+                        }
+                    }
+                    Column::NumNodes => {
+                        field_bytes.extend(summary.num_objects[0].to_string().bytes())
+                    }
+                    Column::NumWays => {
+                        field_bytes.extend(summary.num_objects[1].to_string().bytes())
+                    }
+                    Column::NumRelations => {
+                        field_bytes.extend(summary.num_objects[2].to_string().bytes())
+                    }
+                    Column::AddedKeys | Column::RemovedKeys | Column::ModifiedKeys => {
+                        let keys = match column {
+                            Column::AddedKeys => &summary.added_keys,
+                            Column::RemovedKeys => &summary.removed_keys,
+                            _ => &summary.modified_keys,
+                        };
+                        encode_field(
+                            &top_keys(keys, self.top_keys),
+                            escape,
+                            field_bytes,
+                            &mut self.utf8_bytes_buffer,
+                        );
+                    }
+                    Column::NumAddedKeys | Column::NumRemovedKeys | Column::NumModifiedKeys => {
+                        let keys = match column {
+                            Column::NumAddedKeys => &summary.added_keys,
+                            Column::NumRemovedKeys => &summary.removed_keys,
+                            _ => &summary.modified_keys,
+                        };
+                        field_bytes.extend(keys.values().sum::<u64>().to_string().bytes());
+                    }
+                    Column::TagCountDelta => {
+                        if summary.tag_count_delta > 0 {
+                            field_bytes.push(b'+');
+                        }
+                        field_bytes.extend(summary.tag_count_delta.to_string().bytes());
+                    }
+                    Column::ChangesetTag(changeset_tag) => {
+                        if let Some(v) = self
+                            .changeset_lookup
+                            .as_ref()
+                            .unwrap()
+                            .tags(changeset_id)?
+                            .and_then(|tags| {
+                                tags.into_iter()
+                                    .find_map(|(k, v)| (k == *changeset_tag).then_some(v))
+                            })
+                        {
+                            encode_field(&v, escape, field_bytes, &mut self.utf8_bytes_buffer);
+                        }
+                    }
+                    // Checked when parsing the arguments
+                    _ => unreachable!(),
+                }
+                self.output.write_field(column, &self.field_bytes)?;
+            }
+            self.output.write_record(None)?;
+        }
+        Ok(())
+    }
+
+    /// With `LineType::Transition`, write one line per transition, most common first
+    fn write_transitions(&mut self) -> Result<()> {
+        let escape = self.escape;
+        let mut transitions: Vec<_> = std::mem::take(&mut self.transitions).into_iter().collect();
+        transitions.sort_by(|(a, a_count), (b, b_count)| {
+            (&a.0, &a.1)
+                .cmp(&(&b.0, &b.1))
+                .then(b_count.cmp(a_count))
+                .then(a.cmp(b))
+        });
+        let top_n = if self.top_transitions == 0 {
+            usize::MAX
         } else {
-            let (hr, min) = (min / 60, min % 60);
-            if hr < 24 {
-                format!("{}h{:02}m{:02}s", hr, min, sec)
+            self.top_transitions
+        };
+
+        let mut num_in_group = 0;
+        let mut last_group: Option<(SmolStr, SmolStr)> = None;
+        for ((period, key, old_value, new_value), count) in transitions {
+            if last_group
+                .as_ref()
+                .is_some_and(|(p, k)| *p == period && *k == key)
+            {
+                num_in_group += 1;
             } else {
-                let (day, hr) = (hr / 24, hr % 24);
-                format!("{}d{}h{:02}m{:02}s", day, hr, min, sec)
+                last_group = Some((period.clone(), key.clone()));
+                num_in_group = 1;
+            }
+            if num_in_group > top_n {
+                continue;
+            }
+            for column in self.columns.iter() {
+                let field_bytes = &mut self.field_bytes;
+                field_bytes.clear();
+                let utf8_bytes_buffer = &mut self.utf8_bytes_buffer;
+                match column {
+                    Column::Period => field_bytes.extend(period.bytes()),
+                    Column::Key => encode_field(&key, escape, field_bytes, utf8_bytes_buffer),
+                    Column::OldValue => {
+                        encode_field(&old_value, escape, field_bytes, utf8_bytes_buffer)
+                    }
+                    Column::NewValue => {
+                        encode_field(&new_value, escape, field_bytes, utf8_bytes_buffer)
+                    }
+                    Column::Count => field_bytes.extend(count.to_string().bytes()),
+                    // Checked when parsing the arguments
+                    _ => unreachable!(),
+                }
+                self.output.write_field(column, &self.field_bytes)?;
             }
+            self.output.write_record(None)?;
         }
+        Ok(())
     }
-}
 
-struct ChangesetTagLookup {
-    conn: Connection,
-}
+    /// Write the line(s) for this change. `prev` is the previous version of this object (if any).
+    /// With `LineType::Version`, the change is only added to the summary of this version.
+    fn write_change(
+        &mut self,
+        curr: &StringOSMObj,
+        prev: Option<&StringOSMObj>,
+        change: &TagChange,
+    ) -> Result<()> {
+        match self.line_type {
+            LineType::Version => {
+                self.version_summary
+                    .get_or_insert_with(VersionSummary::default)
+                    .add(change);
+                Ok(())
+            }
+            LineType::Changeset => {
+                self.changeset_summaries
+                    .entry(curr.changeset_id().unwrap_or(0))
+                    .or_insert_with(|| ChangesetSummary::new(curr))
+                    .add(curr, change);
+                Ok(())
+            }
+            LineType::Transition => {
+                let period = match self.time_bucket {
+                    Some(time_bucket) => {
+                        let iso_datetime = curr.timestamp().as_ref().unwrap().to_iso_string();
+                        time_bucket.period(&iso_datetime).into()
+                    }
+                    None => SmolStr::default(),
+                };
+                *self
+                    .transitions
+                    .entry((
+                        period,
+                        change.key.into(),
+                        change.last_value.into(),
+                        change.curr_value.into(),
+                    ))
+                    .or_default() += 1;
+                Ok(())
+            }
+            LineType::OldNewValue | LineType::SeparateLines | LineType::Latest => {
+                self.write_line(curr, prev, change)
+            }
+        }
+    }
 
-impl ChangesetTagLookup {
-    fn from_filename(filename: &str) -> Result<Self> {
-        let conn = Connection::open(filename)?;
-        Ok(ChangesetTagLookup { conn })
+    /// With `LineType::Version`, write the line for this version, if it had any changes
+    fn finish_version(&mut self, curr: &StringOSMObj, prev: Option<&StringOSMObj>) -> Result<()> {
+        if self
+            .version_summary
+            .as_ref()
+            .is_none_or(VersionSummary::is_empty)
+        {
+            return Ok(());
+        }
+        // The key & value columns are empty
+        let change = TagChange {
+            key: "",
+            last_value: "",
+            last_value_existed: false,
+            curr_value: "",
+            curr_value_exists: false,
+            last_value_author: None,
+            is_cosmetic: false,
+            renamed_from: None,
+        };
+        let result = self.write_line(curr, prev, &change);
+        self.version_summary = None;
+        result
     }
 
-    fn tags(&self, cid: u32) -> Result<Option<Vec<(String, String)>>> {
-        let res: Option<Vec<u8>> = self
-            .conn
-            .query_row(
-                "select other_tags from changeset_tags where id = ?1;",
-                [cid],
-                |row| row.get(0),
+    fn write_line(
+        &mut self,
+        curr: &StringOSMObj,
+        prev: Option<&StringOSMObj>,
+        change: &TagChange,
+    ) -> Result<()> {
+        let TagChange {
+            key,
+            last_value,
+            last_value_existed,
+            curr_value,
+            curr_value_exists,
+            last_value_author,
+            is_cosmetic,
+            renamed_from,
+        } = *change;
+        let old_key = renamed_from.unwrap_or(key);
+        let (curr_geometry, prev_geometry) = if self.columns.iter().any(Column::is_geometry)
+            || matches!(self.output, RowOutput::GeoJSONSeq { .. })
+        {
+            (
+                self.geometry(curr)?,
+                prev.map(|prev| self.geometry(prev)).transpose()?.flatten(),
             )
-            .optional()?;
-        match res {
-            None => Ok(None),
-            Some(tags) => {
-                let tags: Vec<(String, String)> = serde_json::from_slice(&tags)?;
-                Ok(Some(tags))
+        } else {
+            (None, None)
+        };
+        let region = match (self.region_lookup.as_mut(), curr_geometry) {
+            (Some(region_lookup), Some(geometry)) => Some(region_lookup.region(geometry.centroid)),
+            _ => None,
+        };
+        let field_bytes = &mut self.field_bytes;
+        let utf8_bytes_buffer = &mut self.utf8_bytes_buffer;
+        let escape = self.escape;
+
+        trace!(
+            "Write tag change {} {:?} → {:?} ({}→{})",
+            key, last_value, curr_value, last_value_existed, curr_value_exists,
+        );
+
+        let mut i: u8 = 0;
+
+        loop {
+            match (&self.line_type, i) {
+                (LineType::OldNewValue | LineType::Latest, 0) => {}
+                (LineType::OldNewValue | LineType::Latest, 1) => {
+                    break;
+                }
+                (LineType::OldNewValue | LineType::Latest, _) => {
+                    unreachable!()
+                }
+                (LineType::Version, 0) => {}
+                (LineType::Version, 1) => {
+                    break;
+                }
+                (LineType::Version, _) => {
+                    unreachable!()
+                }
+                (LineType::Changeset | LineType::Transition, _) => {
+                    unreachable!()
+                }
+                (LineType::SeparateLines, 0) => {
+                    if !last_value_existed {
+                        i += 1;
+                        continue;
+                    }
+                }
+                (LineType::SeparateLines, 1) => {
+                    if !curr_value_exists {
+                        i += 1;
+                        continue;
+                    }
+                }
+                (LineType::SeparateLines, 2) => {
+                    break;
+                }
+                (LineType::SeparateLines, _) => {
+                    unreachable!()
+                }
             }
-        }
+
+            for column in self.columns.iter() {
+                field_bytes.clear();
+                match column {
+                    // The old value line of a rename has the old key
+                    Column::Key if i == 0 && matches!(self.line_type, LineType::SeparateLines) => {
+                        encode_field(old_key, escape, field_bytes, utf8_bytes_buffer);
+                    }
+                    Column::Key => {
+                        encode_field(key, escape, field_bytes, utf8_bytes_buffer);
+                    }
+                    Column::OldKey => {
+                        if last_value_existed {
+                            encode_field(old_key, escape, field_bytes, utf8_bytes_buffer);
+                        }
+                    }
+                    Column::NewKey => {
+                        if curr_value_exists {
+                            encode_field(key, escape, field_bytes, utf8_bytes_buffer);
+                        }
+                    }
+                    Column::LifecycleFrom => {
+                        if let Some((prefix, _)) =
+                            lifecycle_key(old_key).filter(|_| last_value_existed)
+                        {
+                            field_bytes.extend(prefix.bytes());
+                        }
+                    }
+                    Column::LifecycleTo => {
+                        if let Some((prefix, _)) = lifecycle_key(key).filter(|_| curr_value_exists)
+                        {
+                            field_bytes.extend(prefix.bytes());
+                        }
+                    }
+                    Column::NewValue => {
+                        encode_field(curr_value, escape, field_bytes, utf8_bytes_buffer);
+                    }
+                    Column::OldValue => {
+                        encode_field(last_value, escape, field_bytes, utf8_bytes_buffer);
+                    }
+                    Column::Value => {
+                        encode_field(
+                            match i {
+                                0 => last_value,
+                                1 => curr_value,
+                                _ => unreachable!(),
+                            },
+                            escape,
+                            field_bytes,
+                            utf8_bytes_buffer,
+                        );
+                    }
+                    Column::Id => {
+                        field_bytes.extend(
+                            format!("{:?}{}", curr.object_type(), curr.id())
+                                .as_str()
+                                .bytes(),
+                        );
+                    }
+                    Column::RawId => field_bytes.extend(curr.id().to_string().as_str().bytes()),
+                    Column::NewVersion => {
+                        field_bytes.extend(curr.version().unwrap().to_string().bytes());
+                    }
+                    Column::OldVersion => {
+                        if let Some(prev) = prev {
+                            field_bytes.extend(prev.version().unwrap().to_string().bytes());
+                        }
+                    }
+                    Column::IsoDatetime => {
+                        field_bytes
+                            .extend(curr.timestamp().as_ref().unwrap().to_iso_string().bytes());
+                    }
+                    Column::EpochDatetime => {
+                        field_bytes.extend(
+                            curr.timestamp()
+                                .as_ref()
+                                .unwrap()
+                                .to_epoch_number()
+                                .to_string()
+                                .bytes(),
+                        );
+                    }
+                    Column::DatetimeFmt(_tz_name, tz, fmt) => {
+                        let datetime = chrono::DateTime::from_timestamp_secs(
+                            curr.timestamp().as_ref().unwrap().to_epoch_number(),
+                        )
+                        .unwrap();
+                        let datetime = datetime.with_timezone(&tz);
+
+                        let res = datetime.format(fmt).to_string();
+                        field_bytes.extend(res.bytes());
+                    }
+                    Column::Username => {
+                        encode_field(curr.user().unwrap(), escape, field_bytes, utf8_bytes_buffer);
+                    }
+                    Column::Uid => {
+                        field_bytes.extend(curr.uid().unwrap().to_string().bytes());
+                    }
+                    Column::ChangesetId => {
+                        field_bytes.extend(curr.changeset_id().unwrap().to_string().bytes());
+                    }
+                    Column::ObjectTypeShort => {
+                        field_bytes.extend(match curr.object_type() {
+                            OSMObjectType::Node => b"n",
+                            OSMObjectType::Way => b"w",
+                            OSMObjectType::Relation => b"r",
+                        });
+                    }
+                    Column::ObjectTypeLong => {
+                        field_bytes.extend(match curr.object_type() {
+                            OSMObjectType::Node => b"node".iter(),
+                            OSMObjectType::Way => b"way".iter(),
+                            OSMObjectType::Relation => b"relation".iter(),
+                        });
+                    }
+                    Column::ChangesetTag(changeset_tag) => {
+                        match self
+                            .changeset_lookup
+                            .as_ref()
+                            .unwrap()
+                            .tags(curr.changeset_id().unwrap())?
+                        {
+                            None => {
+                                trace!("No tags found for changeset {:?}", curr.changeset_id());
+                            }
+                            Some(tags_for_changeset) => {
+                                if let Some(v) = tags_for_changeset
+                                    .iter()
+                                    .filter_map(
+                                        |(k, v)| {
+                                            if k == changeset_tag { Some(v) } else { None }
+                                        },
+                                    )
+                                    .next()
+                                {
+                                    encode_field(v, escape, field_bytes, utf8_bytes_buffer);
+                                }
+                            }
+                        }
+                    }
+                    Column::Tag(other_key) => {
+                        if let Some(v) = curr.tag(other_key) {
+                            encode_field(v, escape, field_bytes, utf8_bytes_buffer);
+                        }
+                    }
+                    Column::OldTag(other_key) => {
+                        if let Some(v) = prev.and_then(|p| p.tag(other_key)) {
+                            encode_field(v, escape, field_bytes, utf8_bytes_buffer);
+                        }
+                    }
+                    Column::CosmeticChange => {
+                        field_bytes.extend(if is_cosmetic {
+                            b"yes".iter()
+                        } else {
+                            b"no".iter()
+                        });
+                    }
+                    // JSON has no tabs or newlines, but `\` needs to be escaped so it can be unescaped
+                    Column::AllTagsJson => {
+                        encode_field(
+                            &curr.tags_json_string(),
+                            escape,
+                            field_bytes,
+                            utf8_bytes_buffer,
+                        );
+                    }
+                    Column::OldAllTagsJson => {
+                        if let Some(prev) = prev {
+                            encode_field(
+                                &prev.tags_json_string(),
+                                escape,
+                                field_bytes,
+                                utf8_bytes_buffer,
+                            );
+                        }
+                    }
+                    Column::Lat | Column::CentroidLat => {
+                        if let Some(geometry) = curr_geometry {
+                            field_bytes.extend(format_coord(geometry.centroid.0).bytes());
+                        }
+                    }
+                    Column::Lon | Column::CentroidLon => {
+                        if let Some(geometry) = curr_geometry {
+                            field_bytes.extend(format_coord(geometry.centroid.1).bytes());
+                        }
+                    }
+                    Column::OldLat => {
+                        if let Some(geometry) = prev_geometry {
+                            field_bytes.extend(format_coord(geometry.centroid.0).bytes());
+                        }
+                    }
+                    Column::OldLon => {
+                        if let Some(geometry) = prev_geometry {
+                            field_bytes.extend(format_coord(geometry.centroid.1).bytes());
+                        }
+                    }
+                    Column::Point => {
+                        if let Some(Geometry {
+                            centroid: (lat, lon),
+                            ..
+                        }) = curr_geometry
+                        {
+                            field_bytes.extend(
+                                format!("POINT({} {})", format_coord(lon), format_coord(lat))
+                                    .bytes(),
+                            );
+                        }
+                    }
+                    Column::Bbox => {
+                        if let Some(Geometry {
+                            bbox: (min_lat, min_lon, max_lat, max_lon),
+                            ..
+                        }) = curr_geometry
+                        {
+                            field_bytes.extend(
+                                format!(
+                                    "{},{},{},{}",
+                                    format_coord(min_lon),
+                                    format_coord(min_lat),
+                                    format_coord(max_lon),
+                                    format_coord(max_lat)
+                                )
+                                .bytes(),
+                            );
+                        }
+                    }
+                    Column::AddedKeys | Column::RemovedKeys | Column::ModifiedKeys => {
+                        if let Some(summary) = self.version_summary.as_ref() {
+                            let keys = match column {
+                                Column::AddedKeys => &summary.added_keys,
+                                Column::RemovedKeys => &summary.removed_keys,
+                                _ => &summary.modified_keys,
+                            };
+                            encode_field(&keys.join(";"), escape, field_bytes, utf8_bytes_buffer);
+                        }
+                    }
+                    Column::NumAddedKeys | Column::NumRemovedKeys | Column::NumModifiedKeys => {
+                        if let Some(summary) = self.version_summary.as_ref() {
+                            let keys = match column {
+                                Column::NumAddedKeys => &summary.added_keys,
+                                Column::NumRemovedKeys => &summary.removed_keys,
+                                _ => &summary.modified_keys,
+                            };
+                            field_bytes.extend(keys.len().to_string().bytes());
+                        }
+                    }
+                    // Only for `LineType::Changeset` & `LineType::Transition`
+                    Column::FirstIsoDatetime
+                    | Column::LastIsoDatetime
+                    | Column::NumNodes
+                    | Column::NumWays
+                    | Column::NumRelations
+                    | Column::Period
+                    | Column::Count => {}
+                    Column::NumTagsBefore => {
+                        if prev.is_some() {
+                            field_bytes.extend(num_tags(prev).to_string().bytes());
+                        }
+                    }
+                    Column::NumTagsAfter => {
+                        field_bytes.extend(num_tags(Some(curr)).to_string().bytes());
+                    }
+                    Column::Region => {
+                        if let Some(region) = region {
+                            encode_field(region, escape, field_bytes, utf8_bytes_buffer);
+                        }
+                    }
+                    Column::TagCountDelta if self.version_summary.is_some() => {
+                        let delta = num_tags(Some(curr)) as i64 - num_tags(prev) as i64;
+                        if delta > 0 {
+                            field_bytes.push(b'+');
+                        }
+                        field_bytes.extend(delta.to_string().bytes());
+                    }
+                    Column::TagCountDelta => {
+                        field_bytes.extend(match (last_value_existed, curr_value_exists) {
+                            (false, false) => unreachable!(),
+                            (false, true) => b"+1".iter(),
+                            (true, false) => b"-1".iter(),
+                            (true, true) => b"0".iter(),
+                        });
+                    }
+
+                    Column::ValueCountDelta if self.version_summary.is_some() => {}
+                    Column::ValueCountDelta => {
+                        field_bytes.extend(match i {
+                            0 => b"-1".iter(),
+                            1 => b"+1".iter(),
+                            _ => unreachable!(),
+                        });
+                    }
+                    Column::OldUsername => {
+                        if let Some(prev) = prev {
+                            encode_field(
+                                prev.user().unwrap(),
+                                escape,
+                                field_bytes,
+                                utf8_bytes_buffer,
+                            );
+                        }
+                    }
+                    Column::OldUid => {
+                        if let Some(prev) = prev {
+                            field_bytes.extend(prev.uid().unwrap().to_string().bytes());
+                        }
+                    }
+                    Column::OldIsoDatetime => {
+                        if let Some(prev) = prev {
+                            field_bytes
+                                .extend(prev.timestamp().as_ref().unwrap().to_iso_string().bytes());
+                        }
+                    }
+                    Column::OldChangesetId => {
+                        if let Some(prev) = prev {
+                            field_bytes.extend(prev.changeset_id().unwrap().to_string().bytes());
+                        }
+                    }
+                    Column::SecondsSincePreviousVersion => {
+                        if let Some(prev) = prev {
+                            let seconds = curr.timestamp().as_ref().unwrap().to_epoch_number()
+                                - prev.timestamp().as_ref().unwrap().to_epoch_number();
+                            field_bytes.extend(seconds.to_string().bytes());
+                        }
+                    }
+                    Column::ValueAuthorVersion => {
+                        if let Some(author) = last_value_author {
+                            field_bytes.extend(author.version.to_string().bytes());
+                        }
+                    }
+                    Column::ValueAuthorUsername => {
+                        if let Some(author) = last_value_author {
+                            encode_field(&author.user, escape, field_bytes, utf8_bytes_buffer);
+                        }
+                    }
+                    Column::ValueAuthorUid => {
+                        if let Some(author) = last_value_author {
+                            field_bytes.extend(author.uid.to_string().bytes());
+                        }
+                    }
+                    Column::ValueAuthorIsoDatetime => {
+                        if let Some(timestamp) =
+                            last_value_author.and_then(|a| a.timestamp.as_ref())
+                        {
+                            field_bytes.extend(timestamp.to_iso_string().bytes());
+                        }
+                    }
+                    Column::ValueAuthorChangesetId => {
+                        if let Some(author) = last_value_author {
+                            field_bytes.extend(author.changeset_id.to_string().bytes());
+                        }
+                    }
+                    Column::ChangeKind => {
+                        field_bytes.extend(change_kind(curr, prev).bytes());
+                    }
+                    Column::MemberCountDelta => {
+                        if let Some(curr_count) = member_count(curr) {
+                            let last_count = prev.and_then(member_count).unwrap_or(0);
+                            let delta = curr_count as i64 - last_count as i64;
+                            if delta > 0 {
+                                field_bytes.push(b'+');
+                            }
+                            field_bytes.extend(delta.to_string().bytes());
+                        }
+                    }
+                }
+                self.output.write_field(column, field_bytes)?;
+            }
+
+            self.output
+                .write_record(curr_geometry.map(|geometry| geometry.centroid))?;
+
+            i += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// What happened to this object in this version: `create`, `modify`, `delete` or `undelete`
+fn change_kind(curr: &StringOSMObj, prev: Option<&StringOSMObj>) -> &'static str {
+    if curr.deleted() {
+        "delete"
+    } else if prev.is_some_and(|p| p.deleted()) {
+        "undelete"
+    } else if prev.is_none() && curr.version() == Some(1) {
+        "create"
+    } else {
+        // Includes the first version we see of an object, when earlier versions aren't in the
+        // file (e.g. extracts)
+        "modify"
+    }
+}
+
+/// Number of nodes in a way, or members in a relation. `None` for nodes.
+fn member_count(obj: &StringOSMObj) -> Option<usize> {
+    match obj {
+        StringOSMObj::Node(_) => None,
+        StringOSMObj::Way(w) => Some(w.num_nodes()),
+        StringOSMObj::Relation(r) => Some(r.members().len()),
+    }
+}
+
+/// Keys which were removed, & another key added, as (old key, new key).
+///
+/// With `by_lifecycle`, keys with the same base key (e.g. `shop` → `disused:shop`) are paired
+/// (if there are several possible new keys, the first alphabetically). Then with `by_value`, keys
+/// with the same value are paired, but only if exactly one removed & one added key have that
+/// value, and it's not a generic value like `yes`.
+fn renamed_keys<'a>(
+    last_tags: &HashMap<&'a str, &'a str>,
+    curr_tags: &BTreeMap<&'a str, &'a str>,
+    by_value: bool,
+    by_lifecycle: bool,
+) -> Vec<(&'a str, &'a str)> {
+    let mut removed: Vec<(&str, &str)> = last_tags
+        .iter()
+        .filter(|(k, _)| !curr_tags.contains_key(*k))
+        .map(|(k, v)| (*k, *v))
+        .collect();
+    removed.sort();
+    let mut added: Vec<(&str, &str)> = curr_tags
+        .iter()
+        .filter(|(k, _)| !last_tags.contains_key(*k))
+        .map(|(k, v)| (*k, *v))
+        .collect();
+
+    let base_key = |key: &'a str| lifecycle_key(key).map_or(key, |(_, base)| base);
+
+    let mut renames = Vec::new();
+    if by_lifecycle {
+        removed.retain(|(old_key, _)| {
+            match added
+                .iter()
+                .position(|(k, _)| base_key(k) == base_key(old_key))
+            {
+                Some(idx) => {
+                    renames.push((*old_key, added.remove(idx).0));
+                    false
+                }
+                None => true,
+            }
+        });
+    }
+    if by_value {
+        let count =
+            |tags: &[(&str, &str)], value: &str| tags.iter().filter(|(_, v)| *v == value).count();
+        for (old_key, value) in removed.iter() {
+            if GENERIC_VALUES.contains(value)
+                || count(&removed, value) != 1
+                || count(&added, value) != 1
+            {
+                continue;
+            }
+            let (new_key, _) = added.iter().find(|(_, v)| v == value).unwrap();
+            renames.push((*old_key, *new_key));
+        }
+    }
+    renames
+}
+
+/// The items removed from, & added to, a `;` separated list value, as (item, was it added).
+///
+/// Whitespace around items is ignored, and with `--normalise` items are compared after
+/// normalisation (e.g. `Pizza` & `pizza` can be the same item).
+fn value_item_changes<'a>(
+    last_value: &'a str,
+    curr_value: &'a str,
+    normalisation: Normalisation,
+) -> Vec<(&'a str, bool)> {
+    let items = |value: &'a str| -> Vec<(&'a str, Cow<'a, str>)> {
+        let mut items: Vec<_> = value
+            .split(';')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| (item, normalisation.normalise(item)))
+            .collect();
+        items.sort_by(|a, b| a.1.cmp(&b.1));
+        items.dedup_by(|a, b| a.1 == b.1);
+        items
+    };
+    let last_items = items(last_value);
+    let curr_items = items(curr_value);
+    let has_item = |items: &[(&str, Cow<str>)], item: &str| {
+        items.binary_search_by(|i| i.1.as_ref().cmp(item)).is_ok()
+    };
+
+    let mut changes: Vec<(&str, bool)> = last_items
+        .iter()
+        .filter(|(_, norm)| !has_item(&curr_items, norm))
+        .map(|(item, _)| (*item, false))
+        .collect();
+    changes.extend(
+        curr_items
+            .iter()
+            .filter(|(_, norm)| !has_item(&last_items, norm))
+            .map(|(item, _)| (*item, true)),
+    );
+    changes
+}
+
+/// The number of tags of this object version (0 for deleted objects, or no object)
+fn num_tags(obj: Option<&StringOSMObj>) -> usize {
+    obj.filter(|o| !o.deleted()).map_or(0, |o| o.num_tags())
+}
+
+/// The nodes added to/removed from a way, or members added/removed/changed role in a relation,
+/// as (key, old value, new value).
+///
+/// Way nodes have the key `@nodes` and value of the node id (e.g. `n123`). Relation members have
+/// the key `@members`, and the value is member id & role (e.g. `w123=outer`).
+fn member_changes(
+    prev: Option<&StringOSMObj>,
+    curr: &StringOSMObj,
+) -> Vec<(&'static str, Option<String>, Option<String>)> {
+    let mut changes = Vec::new();
+    match curr {
+        StringOSMObj::Node(_) => {}
+        StringOSMObj::Way(curr) => {
+            // How many more (or fewer) times each node is in the way now
+            let mut node_deltas: BTreeMap<i64, i64> = BTreeMap::new();
+            for nid in curr.nodes() {
+                *node_deltas.entry(*nid).or_default() += 1;
+            }
+            if let Some(prev) = prev.and_then(|p| p.as_way()) {
+                for nid in prev.nodes() {
+                    *node_deltas.entry(*nid).or_default() -= 1;
+                }
+            }
+            for (nid, delta) in node_deltas.into_iter() {
+                let value = format!("n{}", nid);
+                for _ in 0..delta.abs() {
+                    if delta > 0 {
+                        changes.push(("@nodes", None, Some(value.clone())));
+                    } else {
+                        changes.push(("@nodes", Some(value.clone()), None));
+                    }
+                }
+            }
+        }
+        StringOSMObj::Relation(curr) => {
+            // For each member, the roles it had before, and has now
+            let mut member_roles = BTreeMap::<_, (Vec<&str>, Vec<&str>)>::new();
+            if let Some(prev) = prev.and_then(|p| p.as_relation()) {
+                for (member_type, mid, role) in prev.members() {
+                    member_roles
+                        .entry((member_type, mid))
+                        .or_default()
+                        .0
+                        .push(role);
+                }
+            }
+            for (member_type, mid, role) in curr.members() {
+                member_roles
+                    .entry((member_type, mid))
+                    .or_default()
+                    .1
+                    .push(role);
+            }
+
+            for ((member_type, mid), (mut old_roles, mut new_roles)) in member_roles.into_iter() {
+                // Unchanged memberships don't matter
+                old_roles.retain(|role| {
+                    if let Some(pos) = new_roles.iter().position(|r| r == role) {
+                        new_roles.remove(pos);
+                        false
+                    } else {
+                        true
+                    }
+                });
+                let value = |role: &str| format!("{:?}{}={}", member_type, mid, role);
+                let mut old_roles = old_roles.into_iter();
+                let mut new_roles = new_roles.into_iter();
+                loop {
+                    match (old_roles.next(), new_roles.next()) {
+                        (None, None) => break,
+                        (old_role, new_role) => {
+                            changes.push(("@members", old_role.map(value), new_role.map(value)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    changes
+}
+
+/// The location of this node (as lat & lon in units of 1e-7 degrees), None for ways, relations &
+/// deleted nodes
+fn node_location(obj: &StringOSMObj) -> Option<(i32, i32)> {
+    if obj.deleted() {
+        // Deleted nodes have no location, even if the file has one (e.g. 0,0)
+        return None;
+    }
+    obj.as_node()
+        .and_then(|n| n.lat_lon())
+        .map(|(lat, lon)| (lat.inner(), lon.inner()))
+}
+
+/// Format a coordinate (in units of 1e-7 degrees) as decimal degrees, e.g. `-1.25`
+fn format_coord(inner: i32) -> String {
+    let sign = if inner < 0 { "-" } else { "" };
+    let inner = inner.unsigned_abs();
+    let fraction = format!("{:07}", inner % 10_000_000);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}{}", sign, inner / 10_000_000)
+    } else {
+        format!("{}{}.{}", sign, inner / 10_000_000, fraction)
+    }
+}
+
+fn encode_field(field: &str, escape: Escape, bytes: &mut Vec<u8>, utf8_bytes_buffer: &mut [u8]) {
+    bytes.clear();
+
+    if escape != Escape::Backslash {
+        bytes.extend(field.bytes());
+        return;
+    }
+
+    for c in field.chars() {
+        if c == '\t' {
+            bytes.push(b'\\');
+            bytes.push(b't');
+        } else if c == '\n' {
+            bytes.push(b'\\');
+            bytes.push(b'n');
+        } else if c == '\r' {
+            bytes.push(b'\\');
+            bytes.push(b'r');
+        } else if c == '\\' {
+            bytes.push(b'\\');
+            bytes.push(b'\\');
+        } else {
+            c.encode_utf8(utf8_bytes_buffer);
+            bytes.extend(&utf8_bytes_buffer[..c.len_utf8()]);
+        }
+    }
+}
+
+fn sorted_objects(a: &impl OSMObj, b: &impl OSMObj) -> std::cmp::Ordering {
+    a.object_type()
+        .cmp(&b.object_type())
+        .then(a.id().cmp(&b.id()))
+        .then(a.version().cmp(&b.version()))
+}
+
+pub fn format_time(duration: &std::time::Duration) -> String {
+    let sec = duration.as_secs_f32().round() as u64;
+    if sec < 60 {
+        format!("{:2}s", sec)
+    } else {
+        let (min, sec) = (sec / 60, sec % 60);
+        if min < 60 {
+            format!("{:2}m{:02}s", min, sec)
+        } else {
+            let (hr, min) = (min / 60, min % 60);
+            if hr < 24 {
+                format!("{}h{:02}m{:02}s", hr, min, sec)
+            } else {
+                let (day, hr) = (hr / 24, hr % 24);
+                format!("{}d{}h{:02}m{:02}s", day, hr, min, sec)
+            }
+        }
+    }
+}
+
+/// The location of an object version
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Geometry {
+    /// Average location of all the nodes, as (lat, lon) in units of 1e-7 degrees
+    centroid: (i32, i32),
+    /// (min lat, min lon, max lat, max lon) in units of 1e-7 degrees
+    bbox: (i32, i32, i32, i32),
+}
+
+impl Geometry {
+    /// The geometry of these (lat, lon) locations, None if there are none
+    fn from_locations(locations: impl IntoIterator<Item = (i32, i32)>) -> Option<Self> {
+        let mut num: i64 = 0;
+        let (mut sum_lat, mut sum_lon) = (0i64, 0i64);
+        let mut bbox = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for (lat, lon) in locations {
+            num += 1;
+            sum_lat += lat as i64;
+            sum_lon += lon as i64;
+            bbox = (
+                bbox.0.min(lat),
+                bbox.1.min(lon),
+                bbox.2.max(lat),
+                bbox.3.max(lon),
+            );
+        }
+        if num == 0 {
+            return None;
+        }
+        Some(Geometry {
+            centroid: ((sum_lat / num) as i32, (sum_lon / num) as i32),
+            bbox,
+        })
+    }
+}
+
+/// A disk backed (SQLite) store of where every node version was, & the nodes of every way
+/// version, so that ways & relations have a location at the time of each version.
+///
+/// The input file is sorted nodes, then ways, then relations, so all the nodes (& ways) have
+/// been added before they are needed.
+struct LocationStore {
+    conn: Connection,
+    /// Objects added since the last commit
+    num_uncommitted: usize,
+    /// The last objects looked up, since each version is often on many lines
+    cache: Vec<(ObjectId, Option<u32>, Option<Geometry>)>,
+}
+
+impl LocationStore {
+    fn new(filename: &str) -> Result<Self> {
+        let conn = Connection::open(filename)
+            .with_context(|| format!("Opening location store {:?}", filename))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = OFF;
+            PRAGMA synchronous = OFF;
+            CREATE TABLE IF NOT EXISTS node_locations (
+                id INTEGER NOT NULL, timestamp INTEGER NOT NULL, version INTEGER NOT NULL,
+                lat INTEGER, lon INTEGER,
+                PRIMARY KEY (id, timestamp, version)
+            ) WITHOUT ROWID;
+            CREATE TABLE IF NOT EXISTS way_nodes (
+                id INTEGER NOT NULL, timestamp INTEGER NOT NULL, version INTEGER NOT NULL,
+                nodes BLOB,
+                PRIMARY KEY (id, timestamp, version)
+            ) WITHOUT ROWID;
+            BEGIN;",
+        )?;
+        Ok(LocationStore {
+            conn,
+            num_uncommitted: 0,
+            cache: Vec::with_capacity(2),
+        })
+    }
+
+    /// Objects without timestamps (e.g. non-history files) are treated as always existing
+    fn timestamp(obj: &StringOSMObj) -> i64 {
+        obj.timestamp().as_ref().map_or(0, |t| t.to_epoch_number())
+    }
+
+    /// Store this object version's location (nodes) or node list (ways)
+    fn add(&mut self, obj: &StringOSMObj) -> Result<()> {
+        let key = (obj.id(), Self::timestamp(obj), obj.version().unwrap_or(0));
+        match obj.object_type() {
+            OSMObjectType::Node => {
+                let location = node_location(obj);
+                self.conn
+                    .prepare_cached(
+                        "INSERT OR REPLACE INTO node_locations (id, timestamp, version, lat, lon) VALUES (?1, ?2, ?3, ?4, ?5);",
+                    )?
+                    .execute(rusqlite::params![
+                        key.0,
+                        key.1,
+                        key.2,
+                        location.map(|l| l.0),
+                        location.map(|l| l.1)
+                    ])?;
+            }
+            OSMObjectType::Way => {
+                let nodes: Option<Vec<u8>> = (!obj.deleted()).then(|| {
+                    obj.as_way()
+                        .unwrap()
+                        .nodes()
+                        .iter()
+                        .flat_map(|nid| nid.to_le_bytes())
+                        .collect()
+                });
+                self.conn
+                    .prepare_cached(
+                        "INSERT OR REPLACE INTO way_nodes (id, timestamp, version, nodes) VALUES (?1, ?2, ?3, ?4);",
+                    )?
+                    .execute(rusqlite::params![key.0, key.1, key.2, nodes])?;
+            }
+            OSMObjectType::Relation => {
+                return Ok(());
+            }
+        }
+
+        self.num_uncommitted += 1;
+        if self.num_uncommitted >= 1_000_000 {
+            self.conn.execute_batch("COMMIT; BEGIN;")?;
+            self.num_uncommitted = 0;
+        }
+        Ok(())
+    }
+
+    /// Write everything to disk
+    fn finish(&mut self) -> Result<()> {
+        self.conn.execute_batch("COMMIT;")?;
+        Ok(())
+    }
+
+    /// Location of this node at this time
+    fn node_location_at(&self, nid: i64, timestamp: i64) -> Result<Option<(i32, i32)>> {
+        let location: Option<(Option<i32>, Option<i32>)> = self
+            .conn
+            .prepare_cached(
+                "SELECT lat, lon FROM node_locations WHERE id = ?1 AND timestamp <= ?2 ORDER BY timestamp DESC, version DESC LIMIT 1;",
+            )?
+            .query_row([nid, timestamp], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        Ok(match location {
+            Some((Some(lat), Some(lon))) => Some((lat, lon)),
+            _ => None,
+        })
+    }
+
+    /// Nodes of this way at this time
+    fn way_nodes_at(&self, wid: i64, timestamp: i64) -> Result<Vec<i64>> {
+        let nodes: Option<Option<Vec<u8>>> = self
+            .conn
+            .prepare_cached(
+                "SELECT nodes FROM way_nodes WHERE id = ?1 AND timestamp <= ?2 ORDER BY timestamp DESC, version DESC LIMIT 1;",
+            )?
+            .query_row([wid, timestamp], |row| row.get(0))
+            .optional()?;
+        Ok(nodes
+            .flatten()
+            .unwrap_or_default()
+            .chunks_exact(8)
+            .map(|b| i64::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Location of this way or relation version, from the nodes at the time of that version.
+    /// Relations use their member nodes & the nodes of their member ways (not sub-relations).
+    fn geometry(&mut self, obj: &StringOSMObj) -> Result<Option<Geometry>> {
+        let oid = ObjectId(obj.object_type(), obj.id());
+        if let Some((_, _, geometry)) = self
+            .cache
+            .iter()
+            .find(|(o, v, _)| *o == oid && *v == obj.version())
+        {
+            return Ok(*geometry);
+        }
+
+        let timestamp = match obj.timestamp() {
+            Some(t) => t.to_epoch_number(),
+            None => i64::MAX,
+        };
+        let mut nids: Vec<i64> = Vec::new();
+        if obj.deleted() {
+            // No location
+        } else if let Some(way) = obj.as_way() {
+            nids.extend(way.nodes());
+        } else if let Some(relation) = obj.as_relation() {
+            for (member_type, mid, _role) in relation.members() {
+                match member_type {
+                    OSMObjectType::Node => nids.push(mid),
+                    OSMObjectType::Way => nids.extend(self.way_nodes_at(mid, timestamp)?),
+                    OSMObjectType::Relation => {}
+                }
+            }
+        }
+        nids.sort_unstable();
+        nids.dedup();
+        let mut locations = Vec::with_capacity(nids.len());
+        for nid in nids {
+            if let Some(location) = self.node_location_at(nid, timestamp)? {
+                locations.push(location);
+            }
+        }
+        let geometry = Geometry::from_locations(locations);
+
+        if self.cache.len() >= 2 {
+            self.cache.remove(0);
+        }
+        self.cache.push((oid, obj.version(), geometry));
+        Ok(geometry)
+    }
+}
+
+/// One region (e.g. country) from the regions file
+struct Region {
+    id: String,
+    /// Position in the file, so the output order is stable
+    idx: usize,
+    geometry: geo::MultiPolygon<f64>,
+    envelope: AABB<[f64; 2]>,
+}
+
+impl RTreeObject for Region {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+/// Finds which region(s) a location is in, from a GeoJSON file of (multi)polygons
+struct RegionLookup {
+    regions: RTree<Region>,
+    /// The last location looked up & the result, since each version is often on many lines
+    last_result: Option<((i32, i32), String)>,
+}
+
+impl RegionLookup {
+    fn from_filename(filename: &str, id_property: &str) -> Result<Self> {
+        let file = BufReader::new(
+            File::open(filename).with_context(|| format!("Opening regions file {:?}", filename))?,
+        );
+        let geojson: geojson::GeoJson = serde_json::from_reader(file)
+            .with_context(|| format!("Reading GeoJSON from regions file {:?}", filename))?;
+        let features = match geojson {
+            geojson::GeoJson::FeatureCollection(fc) => fc.features,
+            geojson::GeoJson::Feature(f) => vec![f],
+            geojson::GeoJson::Geometry(_) => {
+                bail!(
+                    "Regions file {:?} must have features, not a geometry",
+                    filename
+                )
+            }
+        };
+
+        let mut regions = Vec::with_capacity(features.len());
+        for (idx, feature) in features.into_iter().enumerate() {
+            let id = match (feature.property(id_property), feature.id.as_ref()) {
+                (Some(serde_json::Value::String(id)), _) => id.clone(),
+                (Some(id), _) if !id.is_null() => id.to_string(),
+                (_, Some(geojson::feature::Id::String(id))) => id.clone(),
+                (_, Some(geojson::feature::Id::Number(id))) => id.to_string(),
+                _ => {
+                    warn!(
+                        "Region #{} has no {:?} property or id, ignoring",
+                        idx, id_property
+                    );
+                    continue;
+                }
+            };
+            let geometry: geo::MultiPolygon<f64> = match feature
+                .geometry
+                .map(geo::Geometry::<f64>::try_from)
+                .transpose()?
+            {
+                Some(geo::Geometry::Polygon(p)) => p.into(),
+                Some(geo::Geometry::MultiPolygon(mp)) => mp,
+                _ => {
+                    warn!("Region {:?} is not a (multi)polygon, ignoring", id);
+                    continue;
+                }
+            };
+            let Some(bbox) = geometry.bounding_rect() else {
+                continue;
+            };
+            regions.push(Region {
+                id,
+                idx,
+                geometry,
+                envelope: AABB::from_corners(bbox.min().into(), bbox.max().into()),
+            });
+        }
+        info!("Read {} regions from {}", regions.len(), filename);
+
+        Ok(RegionLookup {
+            regions: RTree::bulk_load(regions),
+            last_result: None,
+        })
+    }
+
+    /// The ids of the regions which contain this location (lat & lon in units of 1e-7 degrees),
+    /// `;` separated. Empty if there are none
+    fn region(&mut self, location: (i32, i32)) -> &str {
+        if self
+            .last_result
+            .as_ref()
+            .is_none_or(|(l, _)| *l != location)
+        {
+            let (lat, lon) = location;
+            let point = geo::Point::new(
+                osmio::lat_lon_inner_to_degrees(lon),
+                osmio::lat_lon_inner_to_degrees(lat),
+            );
+            let mut regions: Vec<&Region> = self
+                .regions
+                .locate_in_envelope_intersecting(&AABB::from_point([point.x(), point.y()]))
+                .filter(|r| r.geometry.intersects(&point))
+                .collect();
+            regions.sort_by_key(|r| r.idx);
+            let ids = regions
+                .iter()
+                .map(|r| r.id.as_str())
+                .collect::<Vec<_>>()
+                .join(";");
+            self.last_result = Some((location, ids));
+        }
+        &self.last_result.as_ref().unwrap().1
+    }
+}
+
+struct ChangesetTagLookup {
+    conn: Connection,
+}
+
+impl ChangesetTagLookup {
+    fn from_filename(filename: &str) -> Result<Self> {
+        let conn = Connection::open(filename)?;
+        Ok(ChangesetTagLookup { conn })
+    }
+
+    fn tags(&self, cid: u32) -> Result<Option<Vec<(String, String)>>> {
+        let res: Option<Vec<u8>> = self
+            .conn
+            .query_row(
+                "select other_tags from changeset_tags where id = ?1;",
+                [cid],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match res {
+            None => Ok(None),
+            Some(tags) => {
+                let tags: Vec<(String, String)> = serde_json::from_slice(&tags)?;
+                Ok(Some(tags))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use osmio::obj_types::{StringNodeBuilder, StringRelationBuilder, StringWayBuilder};
+
+    fn node(version: u32, deleted: bool) -> StringOSMObj {
+        let mut n: StringOSMObj = StringNodeBuilder::default()._id(1).build().unwrap().into();
+        n.set_version(version);
+        n.set_deleted(deleted);
+        n
+    }
+
+    fn way(nodes: &[i64]) -> StringOSMObj {
+        let mut w = StringWayBuilder::default()._id(1).build().unwrap();
+        w.set_nodes(nodes.iter().copied());
+        w.into()
+    }
+
+    fn relation(members: &[(OSMObjectType, i64, &str)]) -> StringOSMObj {
+        let mut r = StringRelationBuilder::default()._id(1).build().unwrap();
+        r.set_members(members.iter().copied());
+        r.into()
+    }
+
+    #[test]
+    fn tag_expression_parse() {
+        // (expression, negated, (node, way, relation))
+        let tests = [
+            ("highway", false, (true, true, true)),
+            ("!highway", true, (true, true, true)),
+            ("w/highway", false, (false, true, false)),
+            ("nw/highway", false, (true, true, false)),
+            ("!w/highway", true, (false, true, false)),
+            ("w/!highway", true, (false, true, false)),
+            ("highway!=footway", false, (true, true, true)),
+            ("r//^name/", false, (false, false, true)),
+            // Not object types
+            ("addr/street", false, (true, true, true)),
+        ];
+        for (input, negated, object_types) in tests {
+            let expr: TagExpression = input.parse().unwrap();
+            assert_eq!(expr.negated, negated, "{}", input);
+            assert_eq!(expr.object_types, object_types, "{}", input);
+        }
+    }
+
+    #[test]
+    fn tag_expression_parse_errors() {
+        for input in ["/name", "/name(/", "name~(", "name!~("] {
+            assert!(input.parse::<TagExpression>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn tag_expression_matches() {
+        use OSMObjectType::*;
+        // (expression, key, value, object type, does it match (ignoring negation))
+        let tests = [
+            ("highway", "highway", "primary", Node, true),
+            ("highway", "highways", "primary", Node, false),
+            ("highway=*", "highway", "primary", Node, true),
+            ("highway=primary", "highway", "primary", Node, true),
+            ("highway=primary", "highway", "secondary", Node, false),
+            (
+                "highway=primary,secondary",
+                "highway",
+                "secondary",
+                Node,
+                true,
+            ),
+            ("highway!=footway", "highway", "primary", Node, true),
+            ("highway!=footway", "highway", "footway", Node, false),
+            ("highway!=footway,path", "highway", "path", Node, false),
+            ("name~^Tesco", "name", "Tesco Express", Node, true),
+            ("name~^Tesco", "name", "Big Tesco", Node, false),
+            ("name!~^Tesco", "name", "Big Tesco", Node, true),
+            ("name!~^Tesco", "name", "Tesco Express", Node, false),
+            ("colour=#ff0000", "colour", "#ff0000", Node, true),
+            ("addr:*", "addr:street", "High Street", Node, true),
+            ("addr:*", "address", "High Street", Node, false),
+            ("rawkey:addr:*", "addr:*", "x", Node, true),
+            ("rawkey:addr:*", "addr:street", "x", Node, false),
+            ("/^name:/", "name:en", "x", Node, true),
+            ("/^name:/", "name", "x", Node, false),
+            ("/^name:/=x", "name:en", "x", Node, true),
+            ("/^name:/=x", "name:en", "y", Node, false),
+            ("w/highway", "highway", "primary", Way, true),
+            ("w/highway", "highway", "primary", Node, false),
+            ("nr/highway", "highway", "primary", Relation, true),
+            ("!w/highway", "highway", "primary", Way, true),
+            ("!highway", "highway", "primary", Node, true),
+        ];
+        for (input, key, value, object_type, expected) in tests {
+            let expr: TagExpression = input.parse().unwrap();
+            assert_eq!(
+                expr.tag_matches(key, value, object_type),
+                expected,
+                "{} with {}={}",
+                input,
+                key,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn tag_expression_matches_changes() {
+        let change = TagChange {
+            key: "shop",
+            last_value: "bakery",
+            last_value_existed: true,
+            curr_value: "butcher",
+            curr_value_exists: true,
+            last_value_author: None,
+            is_cosmetic: false,
+            renamed_from: None,
+        };
+        // Values match if the old or new value match, negative values if neither do
+        let tests = [
+            ("shop=bakery", true),
+            ("shop=butcher", true),
+            ("shop=florist", false),
+            ("shop!=bakery", false),
+            ("shop!=florist", true),
+            ("shop~^b", true),
+            ("shop!~^b", false),
+        ];
+        for (input, expected) in tests {
+            let expr: TagExpression = input.parse().unwrap();
+            assert_eq!(
+                expr.change_matches(&change, OSMObjectType::Node),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn strip_comments() {
+        let tests = [
+            ("highway", "highway"),
+            ("  highway  ", "highway"),
+            ("# comment", ""),
+            ("  # comment", ""),
+            ("colour=#ff0000", "colour=#ff0000"),
+            ("name~Store #5", "name~Store #5"),
+            ("  ref=A #2 ", "ref=A #2"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(strip_comment(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn normalise_values() {
+        let all: Normalisation = "trim,case,semicolon".parse().unwrap();
+        let case_semicolon: Normalisation = "case,semicolon".parse().unwrap();
+        let semicolon: Normalisation = "semicolon".parse().unwrap();
+        let tests = [
+            (all, " Pizza ", "pizza"),
+            (all, "b; a ;a", "a;b"),
+            (all, " B ; a ", "a;b"),
+            (case_semicolon, "Yes;yes", "yes"),
+            (case_semicolon, "a;B", "a;b"),
+            (case_semicolon, "A;b", "a;b"),
+            (semicolon, "b;a;a", "a;b"),
+            (semicolon, "B;a", "B;a"),
+            (semicolon, "a; b", " b;a"),
+            (Normalisation::default(), " A;b ", " A;b "),
+        ];
+        for (normalisation, input, expected) in tests {
+            assert_eq!(
+                normalisation.normalise(input),
+                expected,
+                "{:?} {:?}",
+                normalisation,
+                input
+            );
+        }
+        assert_eq!(
+            case_semicolon.normalise("a;B"),
+            case_semicolon.normalise("A;b")
+        );
+    }
+
+    #[test]
+    fn kv_parse() {
+        assert_eq!(
+            parse_kv("amenity=pub").unwrap(),
+            ("amenity".into(), "pub".into())
+        );
+        assert_eq!(parse_kv("note=a=b").unwrap(), ("note".into(), "a=b".into()));
+        assert_eq!(parse_kv("name=").unwrap(), ("name".into(), "".into()));
+        assert!(parse_kv("foo").is_err());
+    }
+
+    #[test]
+    fn object_id_parse() {
+        let ObjectId(object_type, id) = "n123".parse().unwrap();
+        assert!(object_type == OSMObjectType::Node && id == 123);
+        let ObjectId(object_type, id) = " w-5 ".parse().unwrap();
+        assert!(object_type == OSMObjectType::Way && id == -5);
+        let ObjectId(object_type, id) = "r1".parse().unwrap();
+        assert!(object_type == OSMObjectType::Relation && id == 1);
+        let ObjectId(object_type, id) = "123".parse().unwrap();
+        assert!(object_type == OSMObjectType::Node && id == 123);
+        for input in ["", "x123", "n", "nabc", "n 123"] {
+            assert!(input.parse::<ObjectId>().is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn format_coords() {
+        let tests = [
+            (0, "0"),
+            (10_000_000, "1"),
+            (12_500_000, "1.25"),
+            (-12_500_000, "-1.25"),
+            (-1, "-0.0000001"),
+            (1_234_567_890, "123.456789"),
+            (-1_800_000_000, "-180"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(format_coord(input), expected);
+        }
+    }
+
+    #[test]
+    fn change_kinds() {
+        assert_eq!(change_kind(&node(1, false), None), "create");
+        assert_eq!(
+            change_kind(&node(2, false), Some(&node(1, false))),
+            "modify"
+        );
+        // The first version we see isn't always the first version
+        assert_eq!(change_kind(&node(3, false), None), "modify");
+        assert_eq!(change_kind(&node(2, true), Some(&node(1, false))), "delete");
+        assert_eq!(
+            change_kind(&node(3, false), Some(&node(2, true))),
+            "undelete"
+        );
+    }
+
+    #[test]
+    fn renamed_keys_pairs() {
+        let last: HashMap<&str, &str> = [
+            ("addr:street", "Foo"),
+            ("shop", "bakery"),
+            ("name", "X"),
+            ("amenity", "pub"),
+        ]
+        .into_iter()
+        .collect();
+        let curr: BTreeMap<&str, &str> = [
+            ("addr:place", "Foo"),
+            ("disused:shop", "bakery"),
+            ("name", "Y"),
+            ("was:amenity", "yes"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(renamed_keys(&last, &curr, false, false).is_empty());
+        assert_eq!(
+            renamed_keys(&last, &curr, true, false),
+            vec![("addr:street", "addr:place"), ("shop", "disused:shop")]
+        );
+        // Lifecycle pairs are found first, even with different values
+        assert_eq!(
+            renamed_keys(&last, &curr, false, true),
+            vec![("amenity", "was:amenity"), ("shop", "disused:shop")]
+        );
+        assert_eq!(
+            renamed_keys(&last, &curr, true, true),
+            vec![
+                ("amenity", "was:amenity"),
+                ("shop", "disused:shop"),
+                ("addr:street", "addr:place")
+            ]
+        );
+
+        // Generic values, & values shared by several keys, aren't renames
+        let last: HashMap<&str, &str> = [
+            ("oneway", "yes"),
+            ("name:en", "Foo"),
+            ("name:de", "Foo"),
+            ("old_ref", "12"),
+        ]
+        .into_iter()
+        .collect();
+        let curr: BTreeMap<&str, &str> = [("bridge", "yes"), ("name:fr", "Foo"), ("ref", "12")]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            renamed_keys(&last, &curr, true, false),
+            vec![("old_ref", "ref")]
+        );
+    }
+
+    #[test]
+    fn value_item_change_lists() {
+        let none = Normalisation::default();
+        let tests = [
+            ("pizza;burger", "pizza;burger;kebab", vec![("kebab", true)]),
+            ("pizza;burger", "burger", vec![("pizza", false)]),
+            ("pizza; burger", "burger;pizza", vec![]),
+            ("pizza;pizza", "pizza", vec![]),
+            ("", "pizza", vec![("pizza", true)]),
+            ("a;b", "b;c", vec![("a", false), ("c", true)]),
+        ];
+        for (last, curr, expected) in tests {
+            assert_eq!(
+                value_item_changes(last, curr, none),
+                expected,
+                "{:?} → {:?}",
+                last,
+                curr
+            );
+        }
+
+        let case = Normalisation {
+            case_fold: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            value_item_changes("Pizza", "pizza;kebab", case),
+            vec![("kebab", true)]
+        );
+    }
+
+    #[test]
+    fn member_change_lists() {
+        use OSMObjectType::*;
+        assert!(member_changes(None, &node(1, false)).is_empty());
+
+        let changes = member_changes(Some(&way(&[1, 2, 3])), &way(&[1, 3, 4, 1]));
+        assert_eq!(
+            changes,
+            vec![
+                ("@nodes", None, Some("n1".to_string())),
+                ("@nodes", Some("n2".to_string()), None),
+                ("@nodes", None, Some("n4".to_string())),
+            ]
+        );
+        assert_eq!(member_changes(None, &way(&[5])).len(), 1);
+
+        let prev = relation(&[(Way, 1, "outer"), (Way, 2, "inner"), (Node, 3, "")]);
+        let curr = relation(&[(Way, 1, "outer"), (Way, 2, "outer"), (Way, 4, "inner")]);
+        assert_eq!(
+            member_changes(Some(&prev), &curr),
+            vec![
+                ("@members", Some("n3=".to_string()), None),
+                (
+                    "@members",
+                    Some("w2=inner".to_string()),
+                    Some("w2=outer".to_string())
+                ),
+                ("@members", None, Some("w4=inner".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn region_lookup_way() {
+        let regions_filename = std::env::temp_dir().join(format!(
+            "osm-tag-csv-history-test-regions-{}.geojson",
+            std::process::id()
+        ));
+        std::fs::write(
+            &regions_filename,
+            r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","properties":{"id":"NE"},"geometry":{"type":"Polygon","coordinates":[[[0,0],[10,0],[10,10],[0,10],[0,0]]]}},
+            {"type":"Feature","properties":{"id":"SW"},"geometry":{"type":"Polygon","coordinates":[[[-10,-10],[0,-10],[0,0],[-10,0],[-10,-10]]]}},
+            {"type":"Feature","properties":{"id":"small"},"geometry":{"type":"Polygon","coordinates":[[[1,1],[3,1],[3,3],[1,3],[1,1]]]}}
+            ]}"#,
+        )
+        .unwrap();
+        let mut region_lookup =
+            RegionLookup::from_filename(regions_filename.to_str().unwrap(), "id").unwrap();
+        std::fs::remove_file(&regions_filename).unwrap();
+
+        assert!(Column::Region.needs_location_store());
+        let mut location_store = LocationStore::new("").unwrap();
+        for (nid, lat, lon) in [(1, 1.5, 1.5), (2, 2.5, 2.5), (3, -5., -5.), (4, -5., -3.)] {
+            let mut n = StringNodeBuilder::default()._id(nid).build().unwrap();
+            n.set_lat_lon((lat, lon)).unwrap();
+            location_store.add(&n.into()).unwrap();
+        }
+        location_store.finish().unwrap();
+
+        let geometry = location_store.geometry(&way(&[1, 2])).unwrap().unwrap();
+        assert_eq!(geometry.centroid, (20_000_000, 20_000_000));
+        assert_eq!(
+            geometry.bbox,
+            (15_000_000, 15_000_000, 25_000_000, 25_000_000)
+        );
+        assert_eq!(region_lookup.region(geometry.centroid), "NE;small");
+
+        // Different way ids, since the store caches the last versions looked up
+        let mut w = way(&[3, 4, 9]);
+        w.set_id(2);
+        let geometry = location_store.geometry(&w).unwrap().unwrap();
+        assert_eq!(region_lookup.region(geometry.centroid), "SW");
+        let mut w = way(&[9]);
+        w.set_id(3);
+        assert_eq!(location_store.geometry(&w).unwrap(), None);
+        assert_eq!(region_lookup.region((-200_000_000, 0)), "");
+    }
+
+    #[test]
+    fn geojsonseq_line() {
+        let filename = std::env::temp_dir().join(format!(
+            "osm-tag-csv-history-test-{}.geojsonl",
+            std::process::id()
+        ));
+        let mut output = RowOutput::GeoJSONSeq {
+            output: std::io::BufWriter::new(Box::new(File::create(&filename).unwrap())),
+            properties: String::new(),
+        };
+        for (column, value) in [
+            (Column::Key, "ref"),
+            (Column::NewValue, "12"),
+            (Column::OldValue, ""),
+            (Column::NewVersion, "2"),
+            (Column::TagCountDelta, "+1"),
+            (Column::Lat, "1.5"),
+            (Column::Uid, ""),
+        ] {
+            output.write_field(&column, value.as_bytes()).unwrap();
+        }
+        output
+            .write_record(Some((15_000_000, -25_000_000)))
+            .unwrap();
+        output.flush().unwrap();
+        drop(output);
+        let contents = std::fs::read_to_string(&filename).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+
+        assert_eq!(
+            contents,
+            r#"{"type":"Feature","geometry":{"type":"Point","coordinates":[-2.5,1.5]},"properties":{"key":"ref","new_value":"12","old_value":null,"new_version":2,"tag_count_delta":1,"lat":1.5,"uid":null}}"#.to_string()
+                + "\n"
+        );
+    }
+
+    #[test]
+    fn latest_changeset_filter() {
+        let changesets_filename = std::env::temp_dir().join(format!(
+            "osm-tag-csv-history-test-changesets-{}.db",
+            std::process::id()
+        ));
+        let lookup =
+            ChangesetTagLookup::from_filename(changesets_filename.to_str().unwrap()).unwrap();
+        lookup
+            .conn
+            .execute_batch(
+                r#"CREATE TABLE changeset_tags (id INTEGER PRIMARY KEY, other_tags BLOB);
+                INSERT INTO changeset_tags VALUES (1, CAST('[["created_by","JOSM"]]' AS BLOB));
+                INSERT INTO changeset_tags VALUES (2, CAST('[["created_by","iD"]]' AS BLOB));"#,
+            )
+            .unwrap();
+        let changeset_filter = ChangesetFilter {
+            lookup,
+            include_expressions: vec!["created_by=JOSM".parse().unwrap()],
+            exclude_expressions: vec![],
+            last_result: None,
+        };
+
+        let output_filename = std::env::temp_dir().join(format!(
+            "osm-tag-csv-history-test-latest-{}.csv",
+            std::process::id()
+        ));
+        let output: Box<dyn std::io::Write> = Box::new(File::create(&output_filename).unwrap());
+        let row_writer = RowWriter::new(
+            RowOutput::Csv(csv::Writer::from_writer(output)),
+            [
+                Column::Key,
+                Column::NewValue,
+                Column::ValueAuthorChangesetId,
+            ]
+            .into_iter()
+            .collect(),
+            LineType::Latest,
+            None,
+            None,
+            None,
+            Escape::Rfc4180,
+        );
+        let mut processor = Processor {
+            only_include_uids: None,
+            exclude_uids: HashSet::new(),
+            only_include_users: SmallVec::new(),
+            only_include_changesets: None,
+            only_include_ids: None,
+            only_include_types: (true, true, true),
+            include_member_changes: false,
+            include_deletions: false,
+            tag_filter: TagFilter {
+                only_include_keys: SmallVec::new(),
+                only_include_tags: SmallVec::new(),
+                exclude_keys: SmallVec::new(),
+                exclude_tags: SmallVec::new(),
+                only_include_change_kinds: (true, true, true),
+                only_include_from: SmallVec::new(),
+                only_include_to: SmallVec::new(),
+                include_expressions: vec![],
+                exclude_expressions: vec![],
+                normalisation: Normalisation::default(),
+                lifecycle: false,
+            },
+            include_cosmetic_changes: false,
+            split_value_keys: SmallVec::new(),
+            detect_renames: false,
+            object_filter: None,
+            changeset_filter: Some(changeset_filter),
+            track_value_authors: true,
+            value_authors: HashMap::new(),
+            row_writer,
+        };
+
+        // v1 (JOSM) adds both tags, v2 (iD) changes the name
+        let mut v1 = node(1, false);
+        v1.set_changeset_id(1);
+        v1.set_tag("amenity", "pub");
+        v1.set_tag("name", "A");
+        let mut v2 = v1.clone();
+        v2.set_version(2);
+        v2.set_changeset_id(2);
+        v2.set_tag("name", "B");
+        processor.process_history(&[v1, v2]).unwrap();
+        processor.row_writer.finish().unwrap();
+        drop(processor);
+
+        let contents = std::fs::read_to_string(&output_filename).unwrap();
+        std::fs::remove_file(&output_filename).unwrap();
+        std::fs::remove_file(&changesets_filename).unwrap();
+        assert_eq!(contents, "amenity,pub,1\n");
     }
 }